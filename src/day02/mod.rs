@@ -1,22 +1,43 @@
 use itertools::Itertools;
 
+use super::intcode::compile::CompiledProgram;
+use super::intcode::symbolic::SymbolicMachine;
 use super::intcode::*;
 
 fn run_program(input: &Program) -> Program {
     let mut computer = Intcode::new(&input, None);
 
-    computer.run_til_halt();
+    computer.run_til_halt().unwrap();
     computer.program()
 }
 
+/// Solves for the noun and verb symbolically, falling back to trying every pair if the program
+/// can't be executed that way.
 fn find_input(input: &Program, desired_output: i64) -> (i64, i64) {
-    let mut v = input.clone();
+    let mut machine = SymbolicMachine::new(input);
+    machine.symbolic_cell(1, 1..=99);
+    machine.symbolic_cell(2, 1..=99);
+
+    match machine.run().and_then(|()| machine.solve(&machine.peek(0), desired_output)) {
+        Ok(Some(solution)) => (solution[0], solution[1]),
+        Ok(None) => (0, 0),
+        Err(e) => {
+            println!("Solving symbolically failed ({}), searching instead", e);
+            search_input(input, desired_output)
+        }
+    }
+}
+
+fn search_input(input: &Program, desired_output: i64) -> (i64, i64) {
+    let compiled = CompiledProgram::new(input);
 
     for (i, j) in (1..100_i64).cartesian_product(1..100_i64) {
-        v[1..=2].copy_from_slice(&[i, j]);
+        let mut computer = compiled.instantiate(None);
+        computer.poke(1, i);
+        computer.poke(2, j);
 
-        let out = run_program(&v);
-        if out[0] == desired_output {
+        computer.run_til_halt().unwrap();
+        if computer.machine().peek(0) == desired_output {
             return (i, j);
         }
     }
@@ -2,7 +2,7 @@ use super::intcode::*;
 
 fn run_program(p: &Program, input: &Vec<i64>) -> (Program, Option<i64>) {
     let mut computer = Intcode::new(&p, Some(input));
-    let out = computer.run_til_output();
+    let out = computer.run_til_output().unwrap();
 
     (computer.program(), out)
 }
@@ -1,44 +1,45 @@
+use super::intcode::compile::CompiledProgram;
+use super::intcode::threads::Topology;
 use super::intcode::*;
 use itertools::Itertools;
 
-fn calc_thrust(program: &Program, phase: &Vec<i64>) -> i64 {
+fn calc_thrust(program: &CompiledProgram, phase: &Vec<i64>) -> i64 {
     phase.iter().fold(0, |acc, &p| {
-        let mut c = Intcode::new(program, Some(&vec![p, acc]));
+        let mut c = program.instantiate(Some(&vec![p, acc]));
 
-        c.run_til_output().unwrap()
+        match c.run().unwrap() {
+            RunState::Output(o) => o,
+            s => panic!("No thrust: {:?}", s),
+        }
     })
 }
 
 fn feedback(program: &Program, phase: &Vec<i64>) -> i64 {
-    let mut amps = phase
-        .iter()
-        .map(|&p| Intcode::new(program, Some(&vec![p])))
-        .collect::<Vec<_>>();
-
-    let mut out = 0;
-    while let Some(o) = amps
-        .iter_mut()
-        .fold(Some(out), |acc, c| {
-            c.input.push_back(acc.unwrap_or(0));
+    let mut topology = Topology::new();
+    let amps = phase.iter().map(|&p| topology.add(program, &[p])).collect::<Vec<_>>();
 
-            c.run_til_output()
-        }) {
-        out = o;
+    for (&from, &to) in amps.iter().zip(amps.iter().cycle().skip(1)) {
+        topology.connect(from, to);
     }
 
-    out
+    let thrust = topology.tap(amps[amps.len() - 1]);
+    topology.feed(amps[0]).send(0).unwrap();
+    topology.spawn().join().unwrap();
+
+    thrust.try_iter().last().unwrap()
 }
 
 pub fn run(input_str: &str) {
     println!("\n-- Day 7 --");
 
-    let input = super::parse_intcode_program(input_str);
+    let program = super::parse_intcode_program(input_str);
+    let input = CompiledProgram::new(&program);
 
     //==============================================================================================
     // Part 1
-    assert_eq!(43210, calc_thrust(&vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0], &vec![4, 3, 2, 1, 0]));
-    assert_eq!(54321, calc_thrust(&vec![3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0], &vec![0, 1, 2, 3, 4]));
-    assert_eq!(65210, calc_thrust(&vec![3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1, 33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0], &vec![1, 0, 4, 3, 2]));
+    assert_eq!(43210, calc_thrust(&CompiledProgram::new(&vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0]), &vec![4, 3, 2, 1, 0]));
+    assert_eq!(54321, calc_thrust(&CompiledProgram::new(&vec![3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0]), &vec![0, 1, 2, 3, 4]));
+    assert_eq!(65210, calc_thrust(&CompiledProgram::new(&vec![3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33, 1002, 33, 7, 33, 1, 33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0]), &vec![1, 0, 4, 3, 2]));
 
     let max = [0, 1, 2, 3, 4]
         .iter()
@@ -58,7 +59,7 @@ pub fn run(input_str: &str) {
         .iter()
         .cloned()
         .permutations(5)
-        .map(|v| feedback(&input, &v))
+        .map(|v| feedback(&program, &v))
         .max();
 
     println!("Part 2: {}", max.unwrap());
@@ -5,7 +5,7 @@ use super::intcode::*;
 fn run_program(p: &Program, input: Option<&[i64]>) -> VecDeque<i64> {
     let mut computer = Intcode::new(p, input);
 
-    computer.run_til_halt();
+    computer.run_til_halt().unwrap();
     computer.output
 }
 
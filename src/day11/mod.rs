@@ -15,7 +15,7 @@ fn painted_tiles(input: &Program, init_col: i64) -> HashMap<C, i64> {
         .try_fold((C::new(0, 0), C::new(0, 1)), |(pos, dir), _| {
             robot.input.push_back(*points.get(&pos).unwrap_or(&init_col));
 
-            match robot.run_til_num_output(2) {
+            match robot.run_til_num_output(2).unwrap() {
                 Some(mut o) => {
                     points.insert(pos, o.pop_front().unwrap());
 
@@ -60,15 +60,35 @@ fn play_game(input: &Program) -> usize {
 
     let mut framebuffer = vec![0; wx * wy];
 
-    let game = input.iter().enumerate().map(|(i, &x)| if i == 0 { 2 } else { x }).collect();
+    let game: Program = input.iter().enumerate().map(|(i, &x)| if i == 0 { 2 } else { x }).collect();
     let mut computer = Intcode::new(&game, None);
 
     let mut ball: Option<i64> = None;
     let mut paddle: Option<i64> = None;
     let mut score = 0;
 
-    while let Some(out) = computer.run_til_num_output(3) {
-        let v = out.into_iter().collect::<Vec<_>>();
+    let mut v = Vec::with_capacity(3);
+
+    loop {
+        match computer.run().unwrap() {
+            RunState::Output(o) => v.push(o),
+            RunState::NeedsInput => {
+                let input = match (paddle, ball) {
+                    (Some(p), Some(b)) => if p < b { 1 } else if p > b { -1 } else { 0 },
+                    _ => 0,
+                };
+
+                computer.input.push_back(input);
+                continue;
+            }
+            RunState::Halted => break,
+            RunState::Watchpoint(_) => continue,
+        }
+
+        if v.len() < 3 {
+            continue;
+        }
+
         let (x, y) = (v[0], v[1]);
 
         if x == -1 {
@@ -83,16 +103,7 @@ fn play_game(input: &Program) -> usize {
             }
         }
 
-        match (paddle, ball) {
-            (Some(p), Some(b)) => {
-                let input = if p < b { 1 } else if p > b { -1 } else { 0 };
-
-                computer.input.clear();
-                computer.input.push_back(input);
-            }
-            _ => {}
-        }
-
+        v.clear();
         window.update_with_buffer(&framebuffer).unwrap();
     }
 
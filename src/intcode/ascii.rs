@@ -0,0 +1,101 @@
+use std::io::{self, BufRead, Write};
+
+use super::{Intcode, IntcodeError, RunState};
+
+/// A piece of output from a program that speaks ASCII.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsciiOutput {
+    /// A line of text, without its newline.
+    Line(String),
+    /// An output outside the ASCII range, e.g. a puzzle answer.
+    Value(i64),
+}
+
+/// Turns a stream of outputs into lines of text and non-ASCII values.
+#[derive(Debug, Default)]
+pub struct AsciiDecoder {
+    line: String,
+}
+
+impl AsciiDecoder {
+    pub fn new() -> Self { AsciiDecoder::default() }
+
+    /// Takes the next output. Returns an item once a line is complete or `x` isn't ASCII; a
+    /// partially decoded line stays buffered in the latter case.
+    pub fn push(&mut self, x: i64) -> Option<AsciiOutput> {
+        match x {
+            10 => Some(AsciiOutput::Line(self.line.drain(..).collect())),
+            0..=127 => {
+                self.line.push(x as u8 as char);
+                None
+            }
+            _ => Some(AsciiOutput::Value(x)),
+        }
+    }
+
+    /// The text of an unterminated line, such as a prompt.
+    pub fn flush(&mut self) -> Option<AsciiOutput> {
+        if self.line.is_empty() {
+            None
+        } else {
+            Some(AsciiOutput::Line(self.line.drain(..).collect()))
+        }
+    }
+}
+
+impl Intcode {
+    /// Pushes the character codes of `s` onto the input queue.
+    pub fn push_str(&mut self, s: &str) {
+        self.input.extend(s.bytes().map(i64::from));
+    }
+
+    /// Pushes `s` followed by a newline, i.e. one command.
+    pub fn push_line(&mut self, s: &str) {
+        self.push_str(s);
+        self.input.push_back(10);
+    }
+
+    /// Runs until the program halts or needs input, and returns what it printed along with the
+    /// state it stopped in.
+    pub fn run_ascii(&mut self) -> Result<(Vec<AsciiOutput>, RunState), IntcodeError> {
+        let (mut decoder, mut printed) = (AsciiDecoder::new(), vec![]);
+
+        loop {
+            match self.run()? {
+                RunState::Output(x) => printed.extend(decoder.push(x)),
+                RunState::Watchpoint(_) => {}
+                state => {
+                    printed.extend(decoder.flush());
+                    return Ok((printed, state));
+                }
+            }
+        }
+    }
+
+    /// Bridges the program to a terminal: prints its text to `out` and feeds it lines read from
+    /// `input` whenever it asks for more, until it halts or `input` ends.
+    pub fn interact<R: BufRead, W: Write>(&mut self, mut input: R, mut out: W) -> io::Result<()> {
+        loop {
+            let (printed, state) = self.run_ascii().map_err(io::Error::other)?;
+
+            for item in printed {
+                match item {
+                    AsciiOutput::Line(line) => writeln!(out, "{}", line)?,
+                    AsciiOutput::Value(x) => writeln!(out, "[{}]", x)?,
+                }
+            }
+
+            if state == RunState::Halted {
+                return Ok(());
+            }
+
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            self.push_line(line.trim_end_matches(['\n', '\r']));
+        }
+    }
+}
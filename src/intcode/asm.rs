@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use super::{decode, Program};
+
+const OPCODES: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+/// An assembly error, with the 1-based source line it was found on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+enum Value {
+    Number(i64),
+    Label { name: String, offset: i64 },
+}
+
+enum Cell {
+    Instruction(i64),
+    Operand(Value),
+}
+
+/// Looks up the opcode and number of cells of a mnemonic, using the same decoding as the VM so
+/// the two can never disagree.
+fn lookup(mnemonic: &str) -> Option<(i64, usize)> {
+    OPCODES.iter().find_map(|&opcode| {
+        let read = |a: usize| if a == 0 { opcode } else { 0 };
+
+        match decode(read, 0) {
+            Ok((op, len)) if op.mnemonic().eq_ignore_ascii_case(mnemonic) => Some((opcode, len)),
+            _ => None,
+        }
+    })
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if let Ok(x) = s.parse::<i64>() {
+        return Ok(Value::Number(x));
+    }
+
+    let (name, offset) = match s.find(['+', '-']) {
+        Some(i) => match s[i..].trim_start_matches('+').parse::<i64>() {
+            Ok(offset) => (s[..i].trim_end(), offset),
+            Err(_) => return Err(format!("invalid offset in '{}'", s)),
+        },
+        None => (s, 0),
+    };
+
+    if is_identifier(name) {
+        Ok(Value::Label { name: name.to_string(), offset })
+    } else {
+        Err(format!("invalid value '{}'", s))
+    }
+}
+
+fn parse_operand(s: &str) -> Result<(i64, Value), String> {
+    let mode = match s.chars().next() {
+        Some('@') => 0,
+        Some('#') => 1,
+        Some('%') => 2,
+        _ => return Err(format!("operand '{}' needs a mode sigil (@, # or %)", s)),
+    };
+
+    Ok((mode, parse_value(s[1..].trim())?))
+}
+
+/// Assembles a program. Each line holds an optional address, an optional `label:`, and then
+/// either a mnemonic with comma separated operands or a `DATA` directive with comma separated
+/// values. Operands take a mode sigil: `@` position, `#` immediate or `%` relative. Values are
+/// numbers or labels with an optional `+n`/`-n` offset, and `;` starts a comment.
+///
+/// The address column makes disassembly listings valid input; when present it must match the
+/// address the line is assembled at.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut cells = Vec::<(usize, Cell)>::new();
+    let mut labels = HashMap::<String, usize>::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let error = |message: String| AsmError { line: line_no, message };
+
+        let mut rest = line.split(';').next().unwrap_or("").trim();
+
+        let first = rest.split_whitespace().next().unwrap_or("");
+        if let Ok(address) = first.parse::<usize>() {
+            if address != cells.len() {
+                return Err(error(format!("address column says {} but line is at {}", address, cells.len())));
+            }
+
+            rest = rest[first.len()..].trim_start();
+        }
+
+        if let Some(i) = rest.find(':') {
+            let label = rest[..i].trim();
+
+            if !is_identifier(label) {
+                return Err(error(format!("invalid label '{}'", label)));
+            }
+            if labels.insert(label.to_string(), cells.len()).is_some() {
+                return Err(error(format!("duplicate label '{}'", label)));
+            }
+
+            rest = rest[i + 1..].trim_start();
+        }
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, ""),
+        };
+
+        let operands = if operands.is_empty() {
+            vec![]
+        } else {
+            operands.split(',').map(str::trim).collect::<Vec<_>>()
+        };
+
+        if mnemonic.eq_ignore_ascii_case("DATA") {
+            if operands.is_empty() {
+                return Err(error("DATA needs at least one value".to_string()));
+            }
+
+            for s in operands {
+                cells.push((line_no, Cell::Operand(parse_value(s).map_err(error)?)));
+            }
+
+            continue;
+        }
+
+        let (opcode, len) = lookup(mnemonic).ok_or_else(|| error(format!("unknown mnemonic '{}'", mnemonic)))?;
+
+        if operands.len() != len - 1 {
+            return Err(error(format!("{} takes {} operands, got {}", mnemonic.to_uppercase(), len - 1, operands.len())));
+        }
+
+        let operands = operands.into_iter().map(parse_operand).collect::<Result<Vec<_>, _>>().map_err(error)?;
+        let modes = operands.iter().rev().fold(0, |acc, (mode, _)| acc * 10 + mode);
+
+        cells.push((line_no, Cell::Instruction(modes * 100 + opcode)));
+        cells.extend(operands.into_iter().map(|(_, v)| (line_no, Cell::Operand(v))));
+    }
+
+    cells
+        .into_iter()
+        .map(|(line, cell)| match cell {
+            Cell::Instruction(x) | Cell::Operand(Value::Number(x)) => Ok(x),
+            Cell::Operand(Value::Label { name, offset }) => match labels.get(&name) {
+                Some(&address) => Ok(address as i64 + offset),
+                None => Err(AsmError { line, message: format!("undefined label '{}'", name) }),
+            },
+        })
+        .collect()
+}
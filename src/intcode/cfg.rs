@@ -0,0 +1,200 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use petgraph::dot::Dot;
+use petgraph::prelude::NodeIndex;
+use petgraph::{algo, Graph};
+
+use super::disasm::render;
+use super::{decode, Op, Param};
+
+/// How a basic block hands control on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Exit {
+    /// Runs into the next block.
+    Fallthrough,
+    /// Ends in a jump with an immediate target, which may or may not be conditional.
+    Jump,
+    /// Ends in a jump whose target is only known at run time.
+    IndirectJump,
+    Halt,
+    /// Runs into a cell that doesn't decode, or off the end of the program.
+    Invalid,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// The address just past the last instruction.
+    pub end: usize,
+    /// The disassembled instructions.
+    pub instructions: Vec<String>,
+    pub exit: Exit,
+}
+
+impl fmt::Display for BasicBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}..{}", self.start, self.end)?;
+        self.instructions.iter().try_for_each(|i| writeln!(f, "{}", i))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Edge {
+    Fallthrough,
+    Jump,
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edge::Fallthrough => Ok(()),
+            Edge::Jump => write!(f, "jump"),
+        }
+    }
+}
+
+/// Where control can go after an instruction: (fallthrough, immediate jump target, indirect).
+fn successors(op: &Op, next: usize) -> (Option<usize>, Option<usize>, bool) {
+    match *op {
+        Op::Halt => (None, None, false),
+        // A constant condition decides the jump statically
+        Op::CondJmp { cond, x: Param::Immediate { x }, dst } => match ((x != 0) == cond, dst) {
+            (false, _) => (Some(next), None, false),
+            (true, Param::Immediate { x: t }) if t >= 0 => (None, Some(t as usize), false),
+            (true, _) => (None, None, true),
+        },
+        Op::CondJmp { dst: Param::Immediate { x: t }, .. } if t >= 0 => (Some(next), Some(t as usize), false),
+        Op::CondJmp { .. } => (Some(next), None, true),
+        _ => (Some(next), None, false),
+    }
+}
+
+/// The static control flow graph of a program: basic blocks reachable from address 0, linked by
+/// fallthrough and immediate jump edges. Jumps through position or relative parameters are left
+/// unresolved, and self-modifying code is analysed as initially loaded.
+pub struct Cfg {
+    pub graph: Graph<BasicBlock, Edge>,
+    pub entry: NodeIndex,
+    /// Addresses of the jumps with targets only known at run time.
+    pub indirect_jumps: Vec<usize>,
+    blocks: HashMap<usize, NodeIndex>,
+}
+
+impl Cfg {
+    pub fn new(program: &[i64]) -> Self {
+        let decode_at = |pc: usize| match decode(|a| program.get(a).cloned().unwrap_or(0), pc) {
+            Ok((op, len)) if pc + len <= program.len() => Some((op, len)),
+            _ => None,
+        };
+
+        // Find the reachable instructions and the addresses that start a block
+        let mut leaders = BTreeSet::new();
+        let mut seen = HashSet::new();
+        let mut todo = vec![0];
+        leaders.insert(0);
+
+        while let Some(pc) = todo.pop() {
+            if !seen.insert(pc) {
+                continue;
+            }
+
+            if let Some((op, len)) = decode_at(pc) {
+                let (next, target, _) = successors(&op, pc + len);
+
+                if let Op::CondJmp { .. } = op {
+                    leaders.extend(next);
+                    leaders.extend(target);
+                }
+
+                todo.extend(next.into_iter().chain(target));
+            }
+        }
+
+        let mut graph = Graph::new();
+        let mut blocks = HashMap::new();
+        let mut indirect_jumps = vec![];
+        let mut edges = vec![];
+
+        for &start in &leaders {
+            let mut block = BasicBlock { start, end: start, instructions: vec![], exit: Exit::Invalid };
+
+            while let Some((op, len)) = decode_at(block.end) {
+                let pc = block.end;
+                block.instructions.push(render(&op, &HashMap::new()));
+                block.end += len;
+
+                let (next, target, indirect) = successors(&op, block.end);
+
+                if let Some(t) = target {
+                    edges.push((start, t, Edge::Jump));
+                }
+                if indirect {
+                    indirect_jumps.push(pc);
+                }
+
+                block.exit = match (next, target.is_some() || indirect) {
+                    (None, false) => Exit::Halt,
+                    (_, true) if indirect => Exit::IndirectJump,
+                    (_, true) => Exit::Jump,
+                    (Some(_), false) => Exit::Fallthrough,
+                };
+
+                match next {
+                    // Stays invalid if the next cell doesn't decode
+                    Some(n) if block.exit == Exit::Fallthrough && !leaders.contains(&n) => block.exit = Exit::Invalid,
+                    Some(n) => {
+                        edges.push((start, n, Edge::Fallthrough));
+                        break;
+                    }
+                    None => break,
+                }
+            }
+
+            blocks.insert(start, graph.add_node(block));
+        }
+
+        for (from, to, edge) in edges {
+            if let (Some(&a), Some(&b)) = (blocks.get(&from), blocks.get(&to)) {
+                graph.add_edge(a, b, edge);
+            }
+        }
+
+        Cfg { entry: blocks[&0], graph, indirect_jumps, blocks }
+    }
+
+    /// The block starting at `address`.
+    pub fn block_at(&self, address: usize) -> Option<NodeIndex> {
+        self.blocks.get(&address).cloned()
+    }
+
+    /// Whether the block at `to` can be reached from the block at `from` without indirect jumps.
+    pub fn reaches(&self, from: usize, to: usize) -> bool {
+        match (self.block_at(from), self.block_at(to)) {
+            (Some(a), Some(b)) => algo::has_path_connecting(&self.graph, a, b, None),
+            _ => false,
+        }
+    }
+
+    /// The loops of the graph, as the start addresses of the blocks of each strongly connected
+    /// component that contains a cycle.
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        let mut loops = algo::kosaraju_scc(&self.graph)
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.graph.find_edge(c[0], c[0]).is_some())
+            .map(|c| {
+                let mut starts = c.into_iter().map(|n| self.graph[n].start).collect::<Vec<_>>();
+                starts.sort();
+                starts
+            })
+            .collect::<Vec<_>>();
+
+        loops.sort();
+        loops
+    }
+
+    /// The graph in Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        format!("{}", Dot::new(&self.graph))
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::VecDeque;
+
+use super::{decode, BudgetedRun, Intcode, IntcodeError, Op, Param, Program, RunState};
+
+/// What a compiled instruction did, so the runner can move on.
+struct Effect {
+    next: usize,
+    wrote: Option<usize>,
+    out: Option<i64>,
+}
+
+/// A compiled instruction. It returns `None`, before changing anything, whenever it would fault
+/// or block, so that the interpreter can take over and produce exactly the same outcome.
+type Block = Box<dyn Fn(&mut Intcode) -> Option<Effect> + Send + Sync>;
+
+trait Load {
+    fn load(m: &Intcode, x: i64) -> Option<i64>;
+}
+
+trait Store {
+    fn address(m: &Intcode, x: i64) -> Option<usize>;
+}
+
+struct Pos;
+struct Imm;
+struct Rel;
+
+fn checked(address: i64) -> Option<usize> {
+    if address < 0 { None } else { Some(address as usize) }
+}
+
+impl Load for Pos {
+    fn load(m: &Intcode, x: i64) -> Option<i64> { checked(x).map(|a| m.memory.get(a)) }
+}
+
+impl Load for Imm {
+    fn load(_: &Intcode, x: i64) -> Option<i64> { Some(x) }
+}
+
+impl Load for Rel {
+    fn load(m: &Intcode, x: i64) -> Option<i64> { checked(m.relative_base.checked_add(x)?).map(|a| m.memory.get(a)) }
+}
+
+impl Store for Pos {
+    fn address(_: &Intcode, x: i64) -> Option<usize> { checked(x) }
+}
+
+impl Store for Rel {
+    fn address(m: &Intcode, x: i64) -> Option<usize> { checked(m.relative_base.checked_add(x)?) }
+}
+
+/// Writing through an immediate parameter always faults, so it is left to the interpreter.
+fn interpret() -> Block {
+    Box::new(|_| None)
+}
+
+fn binary(op: fn(i64, i64) -> i64, x: Param, y: Param, dst: Param, next: usize) -> Block {
+    match x {
+        Param::Address { x } => binary_y::<Pos>(op, x, y, dst, next),
+        Param::Immediate { x } => binary_y::<Imm>(op, x, y, dst, next),
+        Param::Relative { x } => binary_y::<Rel>(op, x, y, dst, next),
+    }
+}
+
+fn binary_y<X: Load + 'static>(op: fn(i64, i64) -> i64, x: i64, y: Param, dst: Param, next: usize) -> Block {
+    match y {
+        Param::Address { x: y } => binary_dst::<X, Pos>(op, x, y, dst, next),
+        Param::Immediate { x: y } => binary_dst::<X, Imm>(op, x, y, dst, next),
+        Param::Relative { x: y } => binary_dst::<X, Rel>(op, x, y, dst, next),
+    }
+}
+
+fn binary_dst<X: Load + 'static, Y: Load + 'static>(op: fn(i64, i64) -> i64, x: i64, y: i64, dst: Param, next: usize) -> Block {
+    match dst {
+        Param::Address { x: d } => binary_block::<X, Y, Pos>(op, x, y, d, next),
+        Param::Relative { x: d } => binary_block::<X, Y, Rel>(op, x, y, d, next),
+        Param::Immediate { .. } => interpret(),
+    }
+}
+
+fn binary_block<X: Load + 'static, Y: Load + 'static, D: Store + 'static>(op: fn(i64, i64) -> i64, x: i64, y: i64, d: i64, next: usize) -> Block {
+    Box::new(move |m| {
+        let val = op(X::load(m, x)?, Y::load(m, y)?);
+        let address = D::address(m, d)?;
+        m.write_mem(address, val);
+
+        Some(Effect { next, wrote: Some(address), out: None })
+    })
+}
+
+fn input(dst: Param, next: usize) -> Block {
+    fn block<D: Store + 'static>(d: i64, next: usize) -> Block {
+        Box::new(move |m| {
+            let address = D::address(m, d)?;
+            let val = m.input.pop_front()?;
+            m.write_mem(address, val);
+
+            Some(Effect { next, wrote: Some(address), out: None })
+        })
+    }
+
+    match dst {
+        Param::Address { x } => block::<Pos>(x, next),
+        Param::Relative { x } => block::<Rel>(x, next),
+        Param::Immediate { .. } => interpret(),
+    }
+}
+
+/// An instruction that only reads its one parameter and then does `f` with the value.
+fn unary(x: Param, f: fn(&mut Intcode, i64, usize) -> Option<Effect>, next: usize) -> Block {
+    fn block<X: Load + 'static>(x: i64, f: fn(&mut Intcode, i64, usize) -> Option<Effect>, next: usize) -> Block {
+        Box::new(move |m| {
+            let val = X::load(m, x)?;
+            f(m, val, next)
+        })
+    }
+
+    match x {
+        Param::Address { x } => block::<Pos>(x, f, next),
+        Param::Immediate { x } => block::<Imm>(x, f, next),
+        Param::Relative { x } => block::<Rel>(x, f, next),
+    }
+}
+
+fn jump(cond: bool, x: Param, dst: Param, next: usize) -> Block {
+    fn block<X: Load + 'static, T: Load + 'static>(cond: bool, x: i64, t: i64, next: usize) -> Block {
+        Box::new(move |m| {
+            let next = if (X::load(m, x)? != 0) == cond { checked(T::load(m, t)?)? } else { next };
+            Some(Effect { next, wrote: None, out: None })
+        })
+    }
+
+    fn with_target<X: Load + 'static>(cond: bool, x: i64, dst: Param, next: usize) -> Block {
+        match dst {
+            Param::Address { x: t } => block::<X, Pos>(cond, x, t, next),
+            Param::Immediate { x: t } => block::<X, Imm>(cond, x, t, next),
+            Param::Relative { x: t } => block::<X, Rel>(cond, x, t, next),
+        }
+    }
+
+    match x {
+        Param::Address { x } => with_target::<Pos>(cond, x, dst, next),
+        Param::Immediate { x } => with_target::<Imm>(cond, x, dst, next),
+        Param::Relative { x } => with_target::<Rel>(cond, x, dst, next),
+    }
+}
+
+fn compile_op(op: Op, next: usize) -> Block {
+    match op {
+        Op::Add { x, y, dst } => binary(i64::wrapping_add, x, y, dst, next),
+        Op::Mul { x, y, dst } => binary(i64::wrapping_mul, x, y, dst, next),
+        Op::CmpLess { x, y, dst } => binary(|x, y| if x < y { 1 } else { 0 }, x, y, dst, next),
+        Op::CmpEq { x, y, dst } => binary(|x, y| if x == y { 1 } else { 0 }, x, y, dst, next),
+        Op::Input { dst } => input(dst, next),
+        Op::Output { out } => unary(out, |_, val, next| Some(Effect { next, wrote: None, out: Some(val) }), next),
+        Op::AdjRelBase { x } => unary(x, |m, val, next| {
+            m.relative_base = m.relative_base.checked_add(val)?;
+            Some(Effect { next, wrote: None, out: None })
+        }, next),
+        Op::CondJmp { cond, x, dst } => jump(cond, x, dst, next),
+        Op::Halt => Box::new(move |m| {
+            m.is_halted = true;
+            Some(Effect { next, wrote: None, out: None })
+        }),
+        // Never decoded ahead of time; left to the interpreter, which knows the handlers
+        Op::Custom { .. } => Box::new(|_| None),
+    }
+}
+
+/// A program translated ahead of time into one specialized closure per address that decodes to
+/// an instruction. It can be instantiated any number of times.
+pub struct CompiledProgram {
+    program: Program,
+    blocks: Vec<Option<Block>>,
+}
+
+impl CompiledProgram {
+    pub fn new(program: &[i64]) -> Self {
+        let read = |a: usize| program.get(a).cloned().unwrap_or(0);
+
+        let blocks = (0..program.len())
+            .map(|pc| match decode(read, pc) {
+                Ok((op, len)) if pc + len <= program.len() => Some(compile_op(op, pc + len)),
+                _ => None,
+            })
+            .collect();
+
+        CompiledProgram { program: program.to_vec(), blocks }
+    }
+
+    pub fn instantiate(&self, init_input: Option<&[i64]>) -> CompiledMachine<'_> {
+        CompiledMachine {
+            compiled: self,
+            machine: Intcode::new(&self.program, init_input),
+            valid: vec![true; self.blocks.len()],
+        }
+    }
+}
+
+/// A machine running a `CompiledProgram`. Instructions that have been overwritten since
+/// compilation, or that fault or block, are run by the interpreter instead, so it behaves
+/// exactly like an `Intcode` running the same program. Machines with tracers, watchpoints or
+/// history recording attached are always interpreted.
+pub struct CompiledMachine<'a> {
+    compiled: &'a CompiledProgram,
+    machine: Intcode,
+    /// Whether the compiled block at each address still matches memory.
+    valid: Vec<bool>,
+}
+
+impl<'a> CompiledMachine<'a> {
+    pub fn machine(&self) -> &Intcode { &self.machine }
+
+    pub fn into_machine(self) -> Intcode { self.machine }
+
+    pub fn input(&mut self) -> &mut VecDeque<i64> { &mut self.machine.input }
+
+    pub fn output(&mut self) -> &mut VecDeque<i64> { &mut self.machine.output }
+
+    /// Writes a memory cell, e.g. to patch the program before running it.
+    pub fn poke(&mut self, address: usize, val: i64) {
+        self.machine.poke(address, val);
+        self.invalidate(address);
+    }
+
+    /// Same as `Intcode::run`.
+    pub fn run(&mut self) -> Result<RunState, IntcodeError> {
+        loop {
+            if let BudgetedRun::Stopped(state) = self.run_with_limit(u64::MAX)? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Same as `Intcode::run_with_limit`.
+    pub fn run_with_limit(&mut self, max_cycles: u64) -> Result<BudgetedRun, IntcodeError> {
+        let limit = self.machine.cycles.saturating_add(max_cycles);
+
+        loop {
+            if self.machine.cycles >= limit && !self.machine.is_halted && self.machine.pending_output.is_none() {
+                return Ok(BudgetedRun::BudgetExhausted);
+            }
+
+            let pc = self.machine.pc;
+            let interpreted = self.machine.is_halted || self.machine.pending_output.is_some() || self.machine.hooks.is_active();
+
+            let effect = match self.compiled.blocks.get(pc) {
+                Some(Some(block)) if self.valid[pc] && !interpreted => block(&mut self.machine),
+                _ => None,
+            };
+
+            match effect {
+                Some(effect) => {
+                    self.machine.pc = effect.next;
+                    self.machine.cycles += 1;
+
+                    if let Some(address) = effect.wrote {
+                        self.invalidate(address);
+                    }
+
+                    if self.machine.is_halted {
+                        return Ok(BudgetedRun::Stopped(RunState::Halted));
+                    }
+
+                    if let Some(x) = effect.out {
+                        return Ok(BudgetedRun::Stopped(RunState::Output(x)));
+                    }
+                }
+                None => {
+                    if let Some(state) = self.interpret()? {
+                        return Ok(BudgetedRun::Stopped(state));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Same as `Intcode::run_til_halt`.
+    pub fn run_til_halt(&mut self) -> Result<(), IntcodeError> {
+        loop {
+            match self.run()? {
+                RunState::Halted => return Ok(()),
+                RunState::NeedsInput => return Err(self.machine.no_input()),
+                RunState::Output(x) => self.machine.emit(x),
+                RunState::Watchpoint(_) => {}
+            }
+        }
+    }
+
+    /// Runs one instruction through the interpreter, invalidating whatever it overwrites.
+    fn interpret(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        let written = self.machine.fetch().ok().and_then(|(op, _)| op.writes()).and_then(|dst| self.machine.resolve(&dst).ok());
+        let state = self.machine.step()?;
+
+        if let Some(address) = written {
+            self.invalidate(address);
+        }
+
+        Ok(state)
+    }
+
+    /// Drops every compiled block that could cover the address.
+    fn invalidate(&mut self, address: usize) {
+        let end = self.valid.len().min(address.saturating_add(1));
+        self.valid[address.saturating_sub(3).min(end)..end].iter_mut().for_each(|v| *v = false);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::memory::Memory;
+use super::{decode_param, InputSource, Intcode, IntcodeError, Op, OutputSink, Param, Word};
+
+/// Runs a custom instruction. An `Err` faults the machine with `IntcodeError::Custom`.
+pub type CustomHandler<W> = Arc<dyn Fn(&CustomContext<W>) -> Result<CustomEffect<W>, String> + Send + Sync>;
+
+/// An additional instruction: `arity` parameters whose modes are taken from the instruction like
+/// any other, the last one being written through if `writes` is set.
+#[derive(Clone)]
+pub struct CustomOp<W = i64> {
+    pub name: &'static str,
+    pub arity: usize,
+    pub writes: bool,
+    pub handler: CustomHandler<W>,
+}
+
+impl<W> fmt::Debug for CustomOp<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}: arity {}, writes: {}>", self.name, self.arity, self.writes)
+    }
+}
+
+/// What a custom instruction gets to see of the machine.
+pub struct CustomContext<'a, W> {
+    pub pc: usize,
+    pub relative_base: i64,
+    /// The values of the parameters that are read, in order.
+    pub args: Vec<W>,
+    memory: &'a Memory<W>,
+}
+
+impl<'a, W: Word> CustomContext<'a, W> {
+    pub fn peek(&self, address: usize) -> W { self.memory.get(address) }
+}
+
+/// What a custom instruction does once it has run. The default carries on with the next
+/// instruction without doing anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomEffect<W> {
+    /// The value to write through the last parameter, if the instruction `writes`.
+    pub write: Option<W>,
+    pub output: Option<W>,
+    pub jump: Option<usize>,
+    pub halt: bool,
+}
+
+impl<W> Default for CustomEffect<W> {
+    fn default() -> Self { CustomEffect { write: None, output: None, jump: None, halt: false } }
+}
+
+/// The opcodes the VM itself implements.
+const BUILTIN_OPCODES: &[i64] = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+impl<W: Word, I: InputSource<W>, O: OutputSink<W>> Intcode<W, I, O> {
+    /// Adds an instruction with the two digit `opcode`, replacing any custom instruction already
+    /// registered with it. Panics if `opcode` is one of the built-in opcodes or not between 0
+    /// and 99.
+    pub fn register_opcode(&mut self, opcode: i64, op: CustomOp<W>) {
+        assert!((0..100).contains(&opcode), "opcode {} is not two digits", opcode);
+        assert!(!BUILTIN_OPCODES.contains(&opcode), "opcode {} is built in", opcode);
+        assert!(op.arity > 0 || !op.writes, "{} writes but has no parameters", op.name);
+
+        self.max_instruction_len = self.max_instruction_len.max(op.arity + 1);
+        self.custom_ops.insert(opcode, op);
+        // Cells that didn't decode before may decode now
+        self.decode_cache = self.decode_cache.as_ref().map(|_| Vec::new());
+    }
+
+    /// Decodes the instruction at `pc` as a custom instruction, if one with its opcode is
+    /// registered.
+    pub(super) fn decode_custom(&self, pc: usize, instruction: i64) -> Option<Result<(Op<W>, usize), IntcodeError>> {
+        self.decode_custom_with(|a| self.read_mem(a), pc, instruction)
+    }
+
+    /// Same as `decode_custom`, reading cells through `read`.
+    pub(super) fn decode_custom_with<V: Word, F: Fn(usize) -> V>(&self, read: F, pc: usize, instruction: i64) -> Option<Result<(Op<V>, usize), IntcodeError>> {
+        let op = self.custom_ops.get(&(instruction % 100))?;
+
+        let params = (1..=op.arity as u32).map(|i| decode_param(&read, pc, instruction, i)).collect::<Result<Vec<_>, _>>();
+
+        Some(params.map(|mut args| {
+            let dst = if op.writes { args.pop() } else { None };
+            (Op::Custom { opcode: instruction % 100, name: op.name, args, dst }, op.arity + 1)
+        }))
+    }
+
+    /// Runs a decoded custom instruction. Returns its output, and the address to continue at if
+    /// it jumps.
+    pub(super) fn execute_custom(&mut self, opcode: i64, args: &[Param<W>], dst: Option<&Param<W>>) -> Result<(Option<W>, Option<usize>), IntcodeError> {
+        let handler = match self.custom_ops.get(&opcode) {
+            Some(op) => Arc::clone(&op.handler),
+            None => return Err(IntcodeError::UnknownOpcode { pc: self.pc, instruction: self.instruction() }),
+        };
+
+        let args = args.iter().map(|x| self.read(x)).collect::<Result<Vec<_>, _>>()?;
+        if let Some(dst) = dst {
+            self.resolve(dst)?;
+        }
+
+        let context = CustomContext { pc: self.pc, relative_base: self.relative_base, args, memory: &self.memory };
+        let effect = handler(&context)
+            .map_err(|message| IntcodeError::Custom { pc: self.pc, instruction: self.instruction(), message })?;
+
+        if let (Some(dst), Some(val)) = (dst, effect.write) {
+            self.write(dst, val)?;
+        }
+
+        if effect.halt {
+            self.is_halted = true;
+        }
+
+        Ok((effect.output, effect.jump))
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use super::loader;
+use super::snapshot::SnapshotError;
+use super::watch::{WatchHit, WatchKind};
+use super::{Intcode, IntcodeError, RunState};
+
+/// How many instructions the debugger can step back by default.
+const HISTORY_LIMIT: usize = 100_000;
+
+const HELP: &str = "\
+step [n]            execute n instructions (default 1)
+continue            run until a breakpoint, halt, fault or missing input
+back [n]            undo n instructions (default 1)
+rewind <n>          go back to the state after instruction n
+lastwrite <addr>    go back to just before the last write to addr
+history [n]         show how far back you can go, or keep the last n instructions (0 turns it off)
+break <addr>        set a breakpoint
+delete <addr>       clear a breakpoint
+breaks              list breakpoints
+watch <addr> [end]  stop after a write to addr (or addr..=end)
+rwatch <addr> [end] stop after a read
+awatch <addr> [end] stop after a read or write
+unwatch <addr>      clear the watchpoints covering addr
+watches             list watchpoints
+regs                show pc, relative base and the I/O queues
+mem <addr> [n]      show n memory cells (default 8)
+set <addr> <v>...   write values to memory starting at addr
+input <v>...        push values onto the input queue
+list [n]            disassemble n instructions from pc (default 5)
+save <file>         save the machine state to a snapshot file
+load <file>         replace the machine with one from a snapshot file
+export <file>       write the program as patched so far, in the format the file name implies
+quit";
+
+/// Why a `step` or `continue` stopped before running its course.
+enum Stop {
+    Breakpoint,
+    Halted,
+    NeedsInput,
+    Fault(IntcodeError),
+    Watch(Vec<WatchHit>),
+}
+
+/// An interactive debugger around a single machine, driven by line based commands.
+pub struct Debugger {
+    machine: Intcode,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+    /// Takes over `machine`, recording the last `HISTORY_LIMIT` instructions from here on so it
+    /// can be stepped backwards.
+    pub fn new(mut machine: Intcode) -> Self {
+        machine.enable_history(HISTORY_LIMIT);
+        Debugger { machine, breakpoints: BTreeSet::new() }
+    }
+
+    pub fn machine(&self) -> &Intcode { &self.machine }
+
+    /// Reads commands from `input` until `quit` or end of input, writing responses to `out`.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut out: W) -> io::Result<()> {
+        self.show_current(&mut out)?;
+        write!(out, "(icdb) ")?;
+        out.flush()?;
+
+        for line in input.lines() {
+            if !self.command(line?.trim(), &mut out)? {
+                break;
+            }
+
+            write!(out, "(icdb) ")?;
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes a single command. Returns `false` once the session should end.
+    pub fn command<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or("");
+
+        if let ("save", Some(path)) | ("load", Some(path)) | ("export", Some(path)) = (cmd, words.clone().next()) {
+            let res = match cmd {
+                "save" => File::create(path).map_err(SnapshotError::Io).and_then(|f| Ok(self.machine.save(BufWriter::new(f))?)),
+                "export" => loader::save_path(path, &self.machine.program()).map_err(SnapshotError::Io),
+                _ => File::open(path).map_err(SnapshotError::Io).and_then(|f| Intcode::load(BufReader::new(f))).map(|mut m| {
+                    if let Some(limit) = self.machine.history_limit() {
+                        m.enable_history(limit);
+                    }
+                    self.machine = m
+                }),
+            };
+
+            match res {
+                Ok(()) => self.show_current(out)?,
+                Err(e) => writeln!(out, "{} failed: {}", cmd, e)?,
+            }
+
+            return Ok(true);
+        }
+
+        let args = words.map(|w| w.parse::<i64>()).collect::<Result<Vec<_>, _>>();
+
+        let args = match args {
+            Ok(args) => args,
+            Err(e) => {
+                writeln!(out, "invalid argument: {}", e)?;
+                return Ok(true);
+            }
+        };
+
+        let address = |i: usize| args.get(i).filter(|&&a| a >= 0).map(|&a| a as usize);
+
+        match (cmd, args.len()) {
+            ("", _) => {}
+            ("s", _) | ("step", _) => {
+                let (n, before) = (args.first().cloned().unwrap_or(1).max(0), self.machine.output.len());
+                let stop = (0..n).find_map(|i| self.step(i == 0));
+                self.report(stop, before, out)?;
+            }
+            ("c", 0) | ("continue", 0) => {
+                let (mut first, before) = (true, self.machine.output.len());
+                let stop = loop {
+                    if let Some(stop) = self.step(first) {
+                        break stop;
+                    }
+                    first = false;
+                };
+                self.report(Some(stop), before, out)?;
+            }
+            ("back", _) => {
+                let n = args.first().cloned().unwrap_or(1).max(0);
+                if !(0..n).all(|_| self.machine.step_back()) {
+                    writeln!(out, "reached the start of the history")?;
+                }
+                self.show_current(out)?;
+            }
+            ("rewind", 1) => {
+                if !self.machine.rewind_to(args[0].max(0) as u64) {
+                    writeln!(out, "reached the start of the history")?;
+                }
+                self.show_current(out)?;
+            }
+            ("lastwrite", 1) => match address(0) {
+                Some(a) => {
+                    if !self.machine.run_back_to_write(a) {
+                        writeln!(out, "no recorded write to {}", a)?;
+                    }
+                    self.show_current(out)?;
+                }
+                None => writeln!(out, "invalid address")?,
+            },
+            ("history", 0) => match self.machine.history_limit() {
+                Some(limit) => writeln!(out, "{} of the last {} instructions recorded", self.machine.history_len(), limit)?,
+                None => writeln!(out, "history is off")?,
+            },
+            ("history", 1) => match args[0] {
+                0 => self.machine.disable_history(),
+                n if n > 0 => self.machine.enable_history(n as usize),
+                _ => writeln!(out, "invalid limit")?,
+            },
+            ("b", 1) | ("break", 1) => match address(0) {
+                Some(a) => { self.breakpoints.insert(a); }
+                None => writeln!(out, "invalid address")?,
+            },
+            ("d", 1) | ("delete", 1) => match address(0) {
+                Some(a) if self.breakpoints.remove(&a) => {}
+                _ => writeln!(out, "no breakpoint at {}", args[0])?,
+            },
+            ("breaks", 0) => writeln!(out, "{:?}", self.breakpoints)?,
+            ("watch", 1) | ("watch", 2) | ("rwatch", 1) | ("rwatch", 2) | ("awatch", 1) | ("awatch", 2) => {
+                let kind = match cmd {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::ReadWrite,
+                };
+
+                match (address(0), address(args.len() - 1)) {
+                    (Some(start), Some(end)) if start <= end => self.machine.add_watchpoint(start..=end, kind),
+                    _ => writeln!(out, "invalid address")?,
+                }
+            }
+            ("unwatch", 1) => match address(0) {
+                Some(a) if self.machine.remove_watchpoints(a) => {}
+                _ => writeln!(out, "no watchpoint at {}", args[0])?,
+            },
+            ("watches", 0) => {
+                for w in self.machine.watchpoints() {
+                    writeln!(out, "{:?} {:?}", w.kind, w.range)?;
+                }
+            }
+            ("r", 0) | ("regs", 0) => {
+                let m = &self.machine;
+                writeln!(out, "pc: {}, relative_base: {}, is_halted: {}, instruction: {}", m.pc(), m.relative_base(), m.is_halted(), m.cycles())?;
+                writeln!(out, "input: {:?}", m.input)?;
+                writeln!(out, "output: {:?}", m.output)?;
+            }
+            ("x", 1) | ("x", 2) | ("mem", 1) | ("mem", 2) => match address(0) {
+                Some(a) => {
+                    let n = args.get(1).cloned().unwrap_or(8).max(0) as usize;
+                    for row in (a..a + n).collect::<Vec<_>>().chunks(8) {
+                        let cells = row.iter().map(|&a| format!(" {:>10}", self.machine.peek(a))).collect::<String>();
+                        writeln!(out, "{:>6}:{}", row[0], cells)?;
+                    }
+                }
+                None => writeln!(out, "invalid address")?,
+            },
+            ("set", n) if n >= 2 => match address(0) {
+                Some(a) => args[1..].iter().enumerate().for_each(|(i, &v)| self.machine.poke(a + i, v)),
+                None => writeln!(out, "invalid address")?,
+            },
+            ("i", _) | ("input", _) => self.machine.input.extend(args.iter()),
+            ("l", _) | ("list", _) => {
+                let mut pc = self.machine.pc();
+                for _ in 0..args.first().cloned().unwrap_or(5) {
+                    match self.machine.instruction_at(pc) {
+                        Some((text, len)) => {
+                            writeln!(out, "{:>6}  {}", pc, text)?;
+                            pc += len;
+                        }
+                        None => {
+                            writeln!(out, "{:>6}  DATA {}", pc, self.machine.peek(pc))?;
+                            pc += 1;
+                        }
+                    }
+                }
+            }
+            ("h", _) | ("help", _) => writeln!(out, "{}", HELP)?,
+            ("q", _) | ("quit", _) => return Ok(false),
+            _ => writeln!(out, "unknown command '{}', try 'help'", line)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Executes one instruction. Breakpoints only stop execution when they're reached after the
+    /// first instruction, so that continuing from a breakpoint makes progress.
+    fn step(&mut self, first: bool) -> Option<Stop> {
+        if !first && self.breakpoints.contains(&self.machine.pc()) {
+            return Some(Stop::Breakpoint);
+        }
+
+        match self.machine.step() {
+            Ok(Some(RunState::Output(x))) => {
+                self.machine.emit(x);
+                None
+            }
+            Ok(Some(RunState::Watchpoint(hits))) => {
+                if let Some(x) = self.machine.pending_output.take() {
+                    self.machine.emit(x);
+                }
+                Some(Stop::Watch(hits))
+            }
+            Ok(Some(RunState::Halted)) => Some(Stop::Halted),
+            Ok(Some(RunState::NeedsInput)) => Some(Stop::NeedsInput),
+            Ok(None) => None,
+            Err(e) => Some(Stop::Fault(e)),
+        }
+    }
+
+    /// Prints why execution stopped, any outputs produced since the output queue held `before`
+    /// values, and the next instruction.
+    fn report<W: Write>(&self, stop: Option<Stop>, before: usize, out: &mut W) -> io::Result<()> {
+        match stop {
+            Some(Stop::Breakpoint) => writeln!(out, "breakpoint at {}", self.machine.pc())?,
+            Some(Stop::Halted) => writeln!(out, "halted")?,
+            Some(Stop::NeedsInput) => writeln!(out, "waiting for input")?,
+            Some(Stop::Fault(e)) => writeln!(out, "fault: {}", e)?,
+            Some(Stop::Watch(hits)) => {
+                for hit in hits {
+                    let text = self.machine.instruction_at(hit.pc).map(|(t, _)| t).unwrap_or_default();
+                    writeln!(out, "watchpoint: {:?} {} = {} by {:>6}  {}", hit.access, hit.address, hit.value, hit.pc, text)?;
+                }
+            }
+            None => {}
+        }
+
+        if self.machine.output.len() > before {
+            writeln!(out, "output: {:?}", self.machine.output.iter().skip(before).collect::<Vec<_>>())?;
+        }
+
+        if self.machine.is_halted() {
+            Ok(())
+        } else {
+            self.show_current(out)
+        }
+    }
+
+    fn show_current<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let pc = self.machine.pc();
+
+        match self.machine.instruction_at(pc) {
+            Some((text, _)) => writeln!(out, "=> {:>6}  {}", pc, text),
+            None => writeln!(out, "=> {:>6}  DATA {}", pc, self.machine.peek(pc)),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{decode, InputSource, Intcode, Op, OutputSink, Param, Word};
+
+/// A line of a disassembly listing: either a decoded instruction or a single `DATA` cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: usize,
+    pub len: usize,
+    pub label: Option<String>,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = self.label.as_ref().map(|l| format!("{}:", l)).unwrap_or_default();
+
+        write!(f, "{:>5}  {:<7}{}", self.address, label, self.text)
+    }
+}
+
+/// Decodes the instruction at `address`, as long as it fits inside the program and is stored in
+/// its canonical form (no stray mode digits), so that reassembling it gives back the same cells.
+fn decode_at(program: &[i64], address: usize) -> Option<(Op, usize)> {
+    let read = |a: usize| program.get(a).cloned().unwrap_or(0);
+
+    match decode(read, address) {
+        Ok((op, len)) if address + len <= program.len() && op.encode()[..] == program[address..address + len] => Some((op, len)),
+        _ => None,
+    }
+}
+
+fn operand(p: Param, labels: &HashMap<usize, String>) -> String {
+    match p {
+        Param::Address { x } => format!("@{}", x),
+        Param::Immediate { x } => match labels.get(&(x as usize)) {
+            Some(l) if x >= 0 => format!("#{}", l),
+            _ => format!("#{}", x),
+        },
+        Param::Relative { x } => format!("%{}", x),
+    }
+}
+
+pub(super) fn render(op: &Op, labels: &HashMap<usize, String>) -> String {
+    let params = op.params();
+
+    // Only immediate jump targets are known statically and get a label
+    let no_labels = HashMap::new();
+    let operands = params
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| match op {
+            Op::CondJmp { .. } if i == 1 => operand(p, labels),
+            _ => operand(p, &no_labels),
+        })
+        .collect::<Vec<_>>();
+
+    if operands.is_empty() {
+        op.mnemonic().to_string()
+    } else {
+        format!("{:<4} {}", op.mnemonic(), operands.join(", "))
+    }
+}
+
+/// Renders the instruction at `address` of a running machine, reading cells through `read`.
+/// Returns `None` if it doesn't decode.
+pub fn instruction_at<F: Fn(usize) -> i64>(read: F, address: usize) -> Option<(String, usize)> {
+    decode(read, address).ok().map(|(op, len)| (render(&op, &HashMap::new()), len))
+}
+
+impl<W: Word, I: InputSource<W>, O: OutputSink<W>> Intcode<W, I, O> {
+    /// Renders the instruction at `address` like `instruction_at`, including instructions added
+    /// with `register_opcode`. Values that don't fit in an `i64` are clamped to its range.
+    pub fn instruction_at(&self, address: usize) -> Option<(String, usize)> {
+        let read = |a: usize| self.read_mem(a).saturate();
+
+        instruction_at(read, address).or_else(|| {
+            let (op, len) = self.decode_custom_with(read, address, read(address)).and_then(Result::ok)?;
+            Some((render(&op, &HashMap::new()), len))
+        })
+    }
+}
+
+/// Disassembles a program by a linear sweep. Cells that don't decode to an instruction become
+/// `DATA`, and every immediate jump target that starts a line gets a generated label.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let mut decoded = Vec::new();
+    let mut address = 0;
+
+    while address < program.len() {
+        let (op, len) = match decode_at(program, address) {
+            Some((op, len)) => (Some(op), len),
+            None => (None, 1),
+        };
+
+        decoded.push((address, len, op));
+        address += len;
+    }
+
+    let mut targets = decoded
+        .iter()
+        .filter_map(|(_, _, op)| match op {
+            Some(Op::CondJmp { dst: Param::Immediate { x }, .. }) if *x >= 0 => Some(*x as usize),
+            _ => None,
+        })
+        .filter(|t| decoded.iter().any(|(a, _, _)| a == t))
+        .collect::<Vec<_>>();
+
+    targets.sort();
+    targets.dedup();
+
+    let labels = targets
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| (t, format!("L{}", i)))
+        .collect::<HashMap<_, _>>();
+
+    decoded
+        .into_iter()
+        .map(|(address, len, op)| Line {
+            address,
+            len,
+            label: labels.get(&address).cloned(),
+            text: match op {
+                Some(op) => render(&op, &labels),
+                None => format!("{:<5}{}", "DATA", program[address]),
+            },
+        })
+        .collect()
+}
+
+/// The disassembly of `program` as text, one line per instruction.
+pub fn listing(program: &[i64]) -> String {
+    disassemble(program).iter().map(|l| format!("{}\n", l)).collect()
+}
@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use super::compile::CompiledProgram;
+use super::{BudgetedRun, Checked, Intcode, IntcodeError, Program, RunState, Word};
+
+/// Cells of random data placed after the code of a generated program.
+const DATA_LEN: usize = 16;
+
+/// A small xorshift generator, so that a failing case can be reproduced from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed over all the bits, so that nearby seeds give unrelated sequences; the
+        // state must never be zero
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)).max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize { (self.next_u64() % n as u64) as usize }
+
+    /// A number in `lo..=hi`.
+    pub fn range(&mut self, lo: i64, hi: i64) -> i64 { lo + (self.next_u64() % (hi - lo + 1) as u64) as i64 }
+
+    /// True one time in `n`.
+    pub fn one_in(&mut self, n: usize) -> bool { self.below(n) == 0 }
+
+    pub fn pick<T: Copy>(&mut self, xs: &[T]) -> T { xs[self.below(xs.len())] }
+
+    /// A number at or near either end of the `i64` range, or another large power of two.
+    pub fn large(&mut self) -> i64 {
+        let near = self.range(0, 3);
+        match self.below(4) {
+            0 => i64::MAX - near,
+            1 => i64::MIN + near,
+            2 => 1 << self.range(32, 62),
+            _ => -(1 << self.range(32, 62)),
+        }
+    }
+}
+
+/// A program and the inputs it is run with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub program: Program,
+    pub input: Vec<i64>,
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |xs: &[i64]| xs.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",");
+        write!(f, "program: {}\ninput: {}", join(&self.program), join(&self.input))
+    }
+}
+
+/// A random program of well-formed instructions followed by a little data. Operands mostly point
+/// into the program, jumps go to instruction boundaries and writes occasionally hit the code, so
+/// runs exercise self-modification, both jump polarities and relative addressing. Now and then an
+/// instruction gets an immediate destination or a bad mode digit, and an operand or data cell a
+/// value near the ends of the `i64` range, to exercise faults and overflow.
+pub fn generate(rng: &mut Rng) -> Case {
+    let opcodes = (0..rng.range(1, 24)).map(|_| if rng.one_in(20) { 99 } else { rng.range(1, 9) }).chain(Some(99));
+    let opcodes = opcodes.collect::<Vec<_>>();
+    let arity = |opcode: i64| match opcode {
+        1 | 2 | 7 | 8 => 3,
+        5 | 6 => 2,
+        3 | 4 | 9 => 1,
+        _ => 0,
+    };
+
+    let mut starts = vec![];
+    let mut code_len = 0;
+    for &opcode in &opcodes {
+        starts.push(code_len as i64);
+        code_len += 1 + arity(opcode);
+    }
+    let len = (code_len + DATA_LEN) as i64;
+
+    let mut program = vec![];
+    for &opcode in &opcodes {
+        let n = arity(opcode);
+        let mut modes = 0;
+        let mut operands = vec![];
+
+        for i in 0..n {
+            let writes = i == 2 || (opcode == 3 && i == 0);
+            let mode = match rng.below(if writes { 2 } else { 3 }) {
+                _ if rng.one_in(50) => rng.pick(&[1, 3]),
+                1 if writes => 2,
+                m => m as i64,
+            };
+
+            let operand = match mode {
+                1 if (opcode == 5 || opcode == 6) && i == 1 => rng.pick(&starts),
+                1 | 2 if rng.one_in(if opcode == 9 { 4 } else { 16 }) => rng.large(),
+                1 if opcode == 9 => rng.range(-3, 3),
+                1 => rng.range(-20, 20),
+                2 => rng.range(-2, len - 1),
+                _ if writes && !rng.one_in(8) => rng.range(code_len as i64, len - 1),
+                _ => rng.range(0, len - 1),
+            };
+
+            modes += mode * 10_i64.pow(i as u32);
+            operands.push(operand);
+        }
+
+        program.push(modes * 100 + opcode);
+        program.extend(operands);
+    }
+    program.extend((0..DATA_LEN).map(|_| match rng.below(8) {
+        0 | 1 => rng.pick(&starts),
+        2 => rng.large(),
+        _ => rng.range(-20, 20),
+    }));
+
+    let input = (0..rng.below(5)).map(|_| rng.range(-20, 20)).collect();
+
+    Case { program, input }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum End {
+    Halted,
+    NeedsInput,
+    OutOfCycles,
+    Fault(IntcodeError),
+}
+
+/// Everything observable about a finished run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub end: End,
+    pub pc: usize,
+    pub relative_base: i64,
+    pub cycles: u64,
+    pub output: Vec<i64>,
+    /// Every non-zero cell, in ascending address order.
+    pub memory: Vec<(usize, i64)>,
+}
+
+/// What arithmetic does when the result doesn't fit in an `i64`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arithmetic {
+    /// Wraps around, like `i64` words.
+    Wrapping,
+    /// Ends the run with `IntcodeError::Overflow`, like `Checked` words.
+    Checked,
+}
+
+/// A straightforward interpreter written from the puzzle descriptions, sharing no code with
+/// `Intcode`, to check it against. Relative base adjustments that overflow always fault.
+pub fn reference(case: &Case, arithmetic: Arithmetic, max_cycles: u64) -> Outcome {
+    let mut memory = case.program.iter().cloned().enumerate().collect::<BTreeMap<usize, i64>>();
+    let mut input = case.input.iter().cloned().collect::<VecDeque<_>>();
+    let mut output = vec![];
+    let (mut pc, mut relative_base, mut cycles) = (0, 0_i64, 0);
+
+    let end = loop {
+        if cycles >= max_cycles {
+            break End::OutOfCycles;
+        }
+
+        let cell = |memory: &BTreeMap<usize, i64>, a: usize| memory.get(&a).cloned().unwrap_or(0);
+        let instruction = cell(&memory, pc);
+        let opcode = instruction % 100;
+        let arity = match opcode {
+            1 | 2 | 7 | 8 => 3,
+            5 | 6 => 2,
+            3 | 4 | 9 => 1,
+            99 => 0,
+            _ => break End::Fault(IntcodeError::UnknownOpcode { pc, instruction }),
+        };
+
+        let mut params = vec![];
+        for i in 1..=arity {
+            let operand = cell(&memory, pc + i);
+            match instruction / 10_i64.pow(i as u32 + 1) % 10 {
+                mode @ 0..=2 => params.push((mode, operand)),
+                _ => break,
+            }
+        }
+        if params.len() < arity {
+            let i = params.len() + 1;
+            let mode = instruction / 10_i64.pow(i as u32 + 1) % 10;
+            break End::Fault(IntcodeError::InvalidMode { pc, instruction, operand: cell(&memory, pc + i), mode });
+        }
+
+        let address = |(mode, operand): (i64, i64)| {
+            let address = if mode == 2 { relative_base.checked_add(operand) } else { Some(operand) };
+            match address {
+                Some(address) if address >= 0 => Ok(address as usize),
+                address => Err(IntcodeError::InvalidAddress { pc, instruction, operand, address: address.unwrap_or(operand) }),
+            }
+        };
+        let read = |memory: &BTreeMap<usize, i64>, param: (i64, i64)| match param {
+            (1, operand) => Ok(operand),
+            param => address(param).map(|a| cell(memory, a)),
+        };
+        let target = |param: (i64, i64)| match param {
+            (1, operand) => Err(IntcodeError::ImmediateWrite { pc, instruction, operand }),
+            param => address(param),
+        };
+        let overflow = IntcodeError::Overflow { pc, instruction };
+
+        let mut next = pc + 1 + arity;
+        let result = match opcode {
+            1 | 2 | 7 | 8 => (|| {
+                let (x, y) = (read(&memory, params[0])?, read(&memory, params[1])?);
+                let value = match (opcode, arithmetic) {
+                    (1, Arithmetic::Wrapping) => x.wrapping_add(y),
+                    (2, Arithmetic::Wrapping) => x.wrapping_mul(y),
+                    (1, Arithmetic::Checked) => x.checked_add(y).ok_or(overflow.clone())?,
+                    (2, Arithmetic::Checked) => x.checked_mul(y).ok_or(overflow.clone())?,
+                    (7, _) => (x < y) as i64,
+                    _ => (x == y) as i64,
+                };
+                let dst = target(params[2])?;
+                memory.insert(dst, value);
+                Ok(true)
+            })(),
+            3 => target(params[0]).map(|dst| match input.pop_front() {
+                Some(x) => {
+                    memory.insert(dst, x);
+                    true
+                }
+                None => false,
+            }),
+            4 => read(&memory, params[0]).map(|x| output.push(x)).map(|_| true),
+            5 | 6 => (|| {
+                if (read(&memory, params[0])? != 0) == (opcode == 5) {
+                    let to = read(&memory, params[1])?;
+                    if to < 0 {
+                        return Err(IntcodeError::InvalidAddress { pc, instruction, operand: params[1].1, address: to });
+                    }
+                    next = to as usize;
+                }
+                Ok(true)
+            })(),
+            9 => read(&memory, params[0]).and_then(|x| {
+                relative_base = relative_base.checked_add(x).ok_or(overflow.clone())?;
+                Ok(true)
+            }),
+            _ => {
+                pc += 1;
+                cycles += 1;
+                break End::Halted;
+            }
+        };
+
+        match result {
+            Ok(true) => {
+                pc = next;
+                cycles += 1;
+            }
+            Ok(false) => break End::NeedsInput,
+            Err(e) => break End::Fault(e),
+        }
+    };
+
+    let memory = memory.into_iter().filter(|&(_, x)| x != 0).collect();
+    Outcome { end, pc, relative_base, cycles, output, memory }
+}
+
+/// Runs a machine with `run_with_limit` until it halts, blocks, faults or has executed
+/// `max_cycles` instructions in all, collecting its outputs.
+fn drive<W: Word, F: FnMut() -> Result<BudgetedRun<W>, IntcodeError>>(mut run: F) -> (End, Vec<i64>) {
+    let mut output = vec![];
+
+    let end = loop {
+        match run() {
+            Ok(BudgetedRun::Stopped(RunState::Output(x))) => output.push(x.saturate()),
+            Ok(BudgetedRun::Stopped(RunState::Watchpoint(_))) => {}
+            Ok(BudgetedRun::Stopped(RunState::Halted)) => break End::Halted,
+            Ok(BudgetedRun::Stopped(RunState::NeedsInput)) => break End::NeedsInput,
+            Ok(BudgetedRun::BudgetExhausted) => break End::OutOfCycles,
+            Err(e) => break End::Fault(e),
+        }
+    };
+
+    (end, output)
+}
+
+fn outcome<W: Word>(machine: &Intcode<W>, (end, output): (End, Vec<i64>)) -> Outcome {
+    Outcome {
+        end,
+        pc: machine.pc,
+        relative_base: machine.relative_base,
+        cycles: machine.cycles,
+        output,
+        memory: machine.memory.cells().into_iter().map(|(a, x)| (a, x.saturate())).collect(),
+    }
+}
+
+/// The execution paths checked against the reference interpreter. All but `checked` run with
+/// `i64` words.
+pub const PATHS: &[&str] = &["uncached", "cached", "compiled", "checked"];
+
+/// The arithmetic of one of `PATHS`.
+pub fn arithmetic(path: &str) -> Arithmetic {
+    if path == "checked" { Arithmetic::Checked } else { Arithmetic::Wrapping }
+}
+
+/// Runs a case through one of `PATHS`.
+pub fn run_path(path: &str, case: &Case, max_cycles: u64) -> Outcome {
+    match path {
+        "compiled" => {
+            let compiled = CompiledProgram::new(&case.program);
+            let mut machine = compiled.instantiate(Some(&case.input));
+            let ran = drive(|| machine.run_with_limit(max_cycles - machine.machine().cycles));
+            outcome(machine.machine(), ran)
+        }
+        "checked" => {
+            let mut machine = Intcode::<Checked>::from_program(&case.program, Some(&case.input));
+            let ran = drive(|| machine.run_with_limit(max_cycles - machine.cycles));
+            outcome(&machine, ran)
+        }
+        _ => {
+            let mut machine = Intcode::new(&case.program, Some(&case.input));
+            machine.set_decode_cache(path == "cached");
+            let ran = drive(|| machine.run_with_limit(max_cycles - machine.cycles));
+            outcome(&machine, ran)
+        }
+    }
+}
+
+/// An execution path that disagreed with the reference interpreter.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub case: Case,
+    pub path: &'static str,
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} run differs from the reference", self.path)?;
+        writeln!(f, "{}", self.case)?;
+        writeln!(f, "expected: {:?}", self.expected)?;
+        write!(f, "actual:   {:?}", self.actual)
+    }
+}
+
+/// Checks every path against the reference interpreter with the same arithmetic. Returns whether
+/// the case overflowed, i.e. whether the checked run ended with `IntcodeError::Overflow`.
+pub fn check(case: &Case, max_cycles: u64) -> Result<bool, Box<Mismatch>> {
+    let wrapping = reference(case, Arithmetic::Wrapping, max_cycles);
+    let checked = reference(case, Arithmetic::Checked, max_cycles);
+
+    for &path in PATHS {
+        let expected = if arithmetic(path) == Arithmetic::Checked { &checked } else { &wrapping };
+        let actual = run_path(path, case, max_cycles);
+        if actual != *expected {
+            return Err(Box::new(Mismatch { case: case.clone(), path, expected: expected.clone(), actual }));
+        }
+    }
+
+    Ok(matches!(checked.end, End::Fault(IntcodeError::Overflow { .. })))
+}
+
+/// Checks `cases` programs generated from `seed`. Returns the number that overflowed.
+pub fn fuzz(seed: u64, cases: usize, max_cycles: u64) -> Result<usize, Box<Mismatch>> {
+    let mut rng = Rng::new(seed);
+    let mut overflowed = 0;
+
+    for _ in 0..cases {
+        if check(&generate(&mut rng), max_cycles)? {
+            overflowed += 1;
+        }
+    }
+
+    Ok(overflowed)
+}
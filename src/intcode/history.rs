@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use super::{Intcode, Word};
+
+/// What it takes to undo one executed instruction.
+#[derive(Debug, Clone)]
+struct Change<W> {
+    pc: usize,
+    relative_base: i64,
+    is_halted: bool,
+    /// Whether the instruction's output was sent to the output queue.
+    emitted: bool,
+    input: Option<W>,
+    /// The address written to, if any, and the value it held before. No instruction writes more
+    /// than one cell.
+    write: Option<(usize, W)>,
+}
+
+/// An undo log of the last `limit` instructions executed since recording began.
+#[derive(Debug, Clone)]
+pub(super) struct History<W> {
+    changes: VecDeque<Change<W>>,
+    limit: usize,
+}
+
+impl<W> History<W> {
+    fn new(limit: usize) -> Self { History { changes: VecDeque::new(), limit } }
+
+    fn trim(&mut self) {
+        while self.changes.len() > self.limit {
+            self.changes.pop_front();
+        }
+    }
+
+    pub(super) fn begin<I, O>(&mut self, machine: &Intcode<W, I, O>) {
+        self.changes.push_back(Change {
+            pc: machine.pc,
+            relative_base: machine.relative_base,
+            is_halted: machine.is_halted,
+            emitted: false,
+            input: None,
+            write: None,
+        });
+        self.trim();
+    }
+
+    /// Drops the change of an instruction that faulted and so never happened.
+    pub(super) fn abort(&mut self) {
+        self.changes.pop_back();
+    }
+
+    pub(super) fn record_input(&mut self, x: W) {
+        if let Some(c) = self.changes.back_mut() {
+            c.input = Some(x);
+        }
+    }
+
+    pub(super) fn record_output(&mut self) {
+        if let Some(c) = self.changes.back_mut() {
+            c.emitted = true;
+        }
+    }
+
+    pub(super) fn record_write(&mut self, address: usize, old: W) {
+        if let Some(c) = self.changes.back_mut() {
+            c.write = Some((address, old));
+        }
+    }
+}
+
+impl<W: Word> Intcode<W> {
+    /// Starts recording an undo log of the last `limit` instructions, which makes it possible to
+    /// step backwards that far. If it is already recording, only the limit changes. Memory
+    /// changed with `poke` is not recorded, and outputs already handed out by `run` can't be
+    /// taken back; only the output queue is rewound.
+    pub fn enable_history(&mut self, limit: usize) {
+        let history = self.hooks.history.get_or_insert_with(|| History::new(limit));
+        history.limit = limit;
+        history.trim();
+    }
+
+    pub fn disable_history(&mut self) {
+        self.hooks.history = None;
+    }
+
+    /// The number of instructions the undo log keeps, or `None` if it isn't recording.
+    pub fn history_limit(&self) -> Option<usize> {
+        self.hooks.history.as_ref().map(|h| h.limit)
+    }
+
+    /// The number of instructions that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.hooks.history.as_ref().map_or(0, |h| h.changes.len())
+    }
+
+    /// Undoes the last executed instruction. Returns `false` if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let change = match self.hooks.history.as_mut().and_then(|h| h.changes.pop_back()) {
+            Some(c) => c,
+            None => return false,
+        };
+
+        if let Some((address, old)) = change.write {
+            self.write_mem(address, old);
+        }
+
+        if let Some(x) = change.input {
+            self.input.push_front(x);
+        }
+
+        if change.emitted {
+            self.output.pop_back();
+        }
+
+        self.pc = change.pc;
+        self.cycles -= 1;
+        self.relative_base = change.relative_base;
+        self.is_halted = change.is_halted;
+        self.pending_output = None;
+
+        true
+    }
+
+    /// Rewinds to the state after `n` instructions in all, i.e. until `cycles` is `n`. Returns
+    /// `false`, having rewound as far as it could, if the history doesn't go back that far.
+    pub fn rewind_to(&mut self, n: u64) -> bool {
+        while self.cycles > n {
+            if !self.step_back() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Steps back until just before the last instruction that wrote to `address`. Returns
+    /// `false`, having rewound all the way, if no recorded instruction did.
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        loop {
+            let wrote = match self.hooks.history.as_ref().and_then(|h| h.changes.back()) {
+                Some(c) => matches!(c.write, Some((a, _)) if a == address),
+                None => return false,
+            };
+
+            self.step_back();
+
+            if wrote {
+                return true;
+            }
+        }
+    }
+}
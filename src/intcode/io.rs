@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+
+/// Where a machine's input instructions get their values from.
+pub trait InputSource<W = i64> {
+    /// The next value, or `None` if there is none yet. The machine then blocks with
+    /// `RunState::NeedsInput` and retries the input instruction when it is run again.
+    fn next_input(&mut self) -> Option<W>;
+}
+
+/// Where a machine's outputs go when they are not handed out by `run`.
+pub trait OutputSink<W = i64> {
+    fn emit(&mut self, x: W);
+}
+
+impl<W> InputSource<W> for VecDeque<W> {
+    fn next_input(&mut self) -> Option<W> { self.pop_front() }
+}
+
+impl<W> OutputSink<W> for VecDeque<W> {
+    fn emit(&mut self, x: W) { self.push_back(x) }
+}
+
+impl<W> OutputSink<W> for Vec<W> {
+    fn emit(&mut self, x: W) { self.push(x) }
+}
+
+/// Blocks until a value is received. A disconnected channel counts as no input.
+impl<W> InputSource<W> for Receiver<W> {
+    fn next_input(&mut self) -> Option<W> { self.recv().ok() }
+}
+
+/// Outputs sent after the receiver hung up are dropped.
+impl<W> OutputSink<W> for Sender<W> {
+    fn emit(&mut self, x: W) { let _ = self.send(x); }
+}
+
+/// Inputs produced by a closure, e.g. to compute a joystick position on demand.
+pub struct FnInput<F>(pub F);
+
+impl<W, F: FnMut() -> Option<W>> InputSource<W> for FnInput<F> {
+    fn next_input(&mut self) -> Option<W> { (self.0)() }
+}
+
+/// Outputs handed to a closure as they are produced.
+pub struct FnOutput<F>(pub F);
+
+impl<W, F: FnMut(W)> OutputSink<W> for FnOutput<F> {
+    fn emit(&mut self, x: W) { (self.0)(x) }
+}
+
+/// Inputs taken from an iterator, in order.
+pub struct IterInput<I>(pub I);
+
+impl<W, I: Iterator<Item = W>> InputSource<W> for IterInput<I> {
+    fn next_input(&mut self) -> Option<W> { self.0.next() }
+}
+
+/// Reads integers separated by whitespace or commas from standard input. Tokens that aren't
+/// integers are reported on standard error and skipped; end of file counts as no input.
+#[derive(Debug, Default)]
+pub struct StdinInput {
+    pending: VecDeque<i64>,
+}
+
+impl StdinInput {
+    pub fn new() -> Self { StdinInput::default() }
+}
+
+impl InputSource for StdinInput {
+    fn next_input(&mut self) -> Option<i64> {
+        let stdin = io::stdin();
+
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+
+            for token in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty()) {
+                match token.parse() {
+                    Ok(x) => self.pending.push_back(x),
+                    Err(_) => eprintln!("ignoring input {:?}: not an integer", token),
+                }
+            }
+        }
+
+        self.pending.pop_front()
+    }
+}
+
+/// Prints each output on its own line to standard output.
+#[derive(Debug, Default)]
+pub struct StdoutOutput;
+
+impl OutputSink for StdoutOutput {
+    fn emit(&mut self, x: i64) {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let _ = writeln!(out, "{}", x).and_then(|_| out.flush());
+    }
+}
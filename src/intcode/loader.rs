@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::asm::{self, AsmError};
+use super::disasm::listing;
+use super::varint::{read_varint, write_varint};
+use super::Program;
+
+const MAGIC: &[u8; 6] = b"ICPROG";
+const VERSION: i64 = 1;
+
+/// The ways a program can be stored.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    /// Comma separated integers, as the puzzles hand them out.
+    Text,
+    /// The magic bytes `ICPROG` followed by zigzag varints: version, length and the cells.
+    Binary,
+    /// A disassembly listing, as written by `disasm::listing`, or any assembly source.
+    Listing,
+}
+
+impl Format {
+    /// The format implied by a file name: `.icb` is binary, `.lst`, `.asm` and `.ica` are
+    /// listings and anything else is text.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Format {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("icb") => Format::Binary,
+            Some("lst") | Some("asm") | Some("ica") => Format::Listing,
+            _ => Format::Text,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// A token in a text program that isn't an integer, or a comma with no value before it.
+    /// `offset` is in bytes from the start of the input; `line` and `column` count from 1.
+    BadToken { offset: usize, line: usize, column: usize, token: String },
+    UnsupportedVersion(i64),
+    Corrupt(&'static str),
+    Listing(AsmError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::BadToken { offset, line, column, token } if token.is_empty() =>
+                write!(f, "missing value at byte {} (line {}, column {})", offset, line, column),
+            LoadError::BadToken { offset, line, column, token } =>
+                write!(f, "invalid value {:?} at byte {} (line {}, column {})", token, offset, line, column),
+            LoadError::UnsupportedVersion(v) => write!(f, "unsupported program version {}", v),
+            LoadError::Corrupt(what) => write!(f, "corrupt program: {}", what),
+            LoadError::Listing(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self { LoadError::Io(e) }
+}
+
+impl From<AsmError> for LoadError {
+    fn from(e: AsmError) -> Self { LoadError::Listing(e) }
+}
+
+/// Parses a text program. Values are separated by commas; whitespace of any kind, including
+/// CRLF line ends and blank lines, may surround them, and a single trailing comma is allowed.
+pub fn parse(text: &str) -> Result<Program, LoadError> {
+    let bad_token = |offset: usize, token: &str| {
+        let before = &text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        LoadError::BadToken { offset, line, column, token: token.to_string() }
+    };
+
+    let mut program = vec![];
+    let mut start = 0;
+    let pieces = text.split(',').map(|piece| {
+        let offset = start;
+        start += piece.len() + 1;
+        (offset, piece)
+    });
+    let pieces = pieces.collect::<Vec<_>>();
+
+    for (i, &(offset, piece)) in pieces.iter().enumerate() {
+        let token = piece.trim();
+        let offset = offset + (piece.len() - piece.trim_start().len());
+
+        if token.is_empty() {
+            // Nothing at all, or nothing after a final comma
+            if pieces.len() == 1 || (i == pieces.len() - 1 && i > 0) {
+                continue;
+            }
+            return Err(bad_token(offset, token));
+        }
+
+        match token.parse::<i64>() {
+            Ok(x) => program.push(x),
+            Err(_) => return Err(bad_token(offset, token)),
+        }
+    }
+
+    Ok(program)
+}
+
+pub fn write_text<W: Write>(mut out: W, program: &[i64]) -> io::Result<()> {
+    writeln!(out, "{}", program.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","))?;
+    out.flush()
+}
+
+pub fn write_binary<W: Write>(mut out: W, program: &[i64]) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    write_varint(&mut out, VERSION)?;
+    write_varint(&mut out, program.len() as i64)?;
+    program.iter().try_for_each(|&x| write_varint(&mut out, x))?;
+    out.flush()
+}
+
+/// Reads a binary program, magic bytes included.
+pub fn read_binary<R: Read>(mut input: R) -> Result<Program, LoadError> {
+    let mut magic = [0; 6];
+    input.read_exact(&mut magic)?;
+
+    if &magic != MAGIC {
+        return Err(LoadError::Corrupt("not a binary program"));
+    }
+
+    match read_varint(&mut input)? {
+        VERSION => {}
+        v => return Err(LoadError::UnsupportedVersion(v)),
+    }
+
+    let len = match read_varint(&mut input)? {
+        n if n < 0 => return Err(LoadError::Corrupt("negative length")),
+        n => n as usize,
+    };
+
+    (0..len).map(|_| Ok(read_varint(&mut input)?)).collect()
+}
+
+pub fn write_listing<W: Write>(mut out: W, program: &[i64]) -> io::Result<()> {
+    out.write_all(listing(program).as_bytes())?;
+    out.flush()
+}
+
+/// Reads a program in `format`. Binary programs are recognized by their magic bytes whatever
+/// `format` says.
+pub fn load<R: Read>(mut input: R, format: Format) -> Result<Program, LoadError> {
+    let mut bytes = vec![];
+    input.read_to_end(&mut bytes)?;
+
+    if bytes.starts_with(MAGIC) {
+        return read_binary(&bytes[..]);
+    }
+
+    let text = String::from_utf8(bytes).map_err(|_| LoadError::Corrupt("neither text nor a binary program"))?;
+    match format {
+        Format::Listing => Ok(asm::assemble(&text)?),
+        _ => parse(&text),
+    }
+}
+
+/// Reads a program from the file at `path` in the format its name implies, or from standard
+/// input as text or binary if `path` is `-`.
+pub fn load_path<P: AsRef<Path>>(path: P) -> Result<Program, LoadError> {
+    let path = path.as_ref();
+
+    if path == Path::new("-") {
+        load(io::stdin().lock(), Format::Text)
+    } else {
+        load(File::open(path)?, Format::from_path(path))
+    }
+}
+
+pub fn save<W: Write>(out: W, program: &[i64], format: Format) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(out, program),
+        Format::Binary => write_binary(out, program),
+        Format::Listing => write_listing(out, program),
+    }
+}
+
+/// Writes a program to the file at `path` in the format its name implies, or to standard output
+/// as text if `path` is `-`.
+pub fn save_path<P: AsRef<Path>>(path: P, program: &[i64]) -> io::Result<()> {
+    let path = path.as_ref();
+
+    if path == Path::new("-") {
+        save(io::stdout().lock(), program, Format::Text)
+    } else {
+        save(BufWriter::new(File::create(path)?), program, Format::from_path(path))
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::Word;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// Pages below this index are reached through a directly indexed table, anything above it
+/// through a hash map, so a stray write far out doesn't allocate a huge directory.
+const MAX_DIRECT_PAGES: usize = 1 << 12;
+
+type Page<W> = Box<[W; PAGE_SIZE]>;
+
+/// Unbounded Intcode memory. Cells are allocated a page at a time on the first write into a
+/// page; every cell that has never been written reads as 0.
+#[derive(Clone)]
+pub struct Memory<W = i64> {
+    direct: Vec<Option<Page<W>>>,
+    sparse: HashMap<usize, Page<W>>,
+}
+
+impl<W> Default for Memory<W> {
+    fn default() -> Self { Memory { direct: vec![], sparse: HashMap::new() } }
+}
+
+impl<W: Word> Memory<W> {
+    pub fn new(image: &[W]) -> Self {
+        let mut memory = Memory::default();
+        image.iter().enumerate().for_each(|(i, x)| memory.set(i, x.clone()));
+
+        memory
+    }
+
+    pub fn get(&self, address: usize) -> W {
+        let (page, offset) = (address >> PAGE_BITS, address & (PAGE_SIZE - 1));
+
+        let page = if page < MAX_DIRECT_PAGES {
+            self.direct.get(page).and_then(|p| p.as_ref())
+        } else {
+            self.sparse.get(&page)
+        };
+
+        page.map_or_else(W::zero, |p| p[offset].clone())
+    }
+
+    pub fn set(&mut self, address: usize, val: W) {
+        let (page, offset) = (address >> PAGE_BITS, address & (PAGE_SIZE - 1));
+        let new_page = || Page::try_from(vec![W::zero(); PAGE_SIZE].into_boxed_slice()).ok().unwrap();
+
+        if val == W::zero() && self.get(address) == W::zero() {
+            return;
+        }
+
+        let page = if page < MAX_DIRECT_PAGES {
+            if page >= self.direct.len() {
+                self.direct.resize_with(page + 1, || None);
+            }
+
+            self.direct[page].get_or_insert_with(new_page)
+        } else {
+            self.sparse.entry(page).or_insert_with(new_page)
+        };
+
+        page[offset] = val;
+    }
+
+    /// Copies out `len` cells starting at `start`.
+    pub fn read_range(&self, start: usize, len: usize) -> Vec<W> {
+        (start..start + len).map(|a| self.get(a)).collect()
+    }
+
+    /// Every non-zero cell in ascending address order.
+    pub fn cells(&self) -> Vec<(usize, W)> {
+        let mut pages = self
+            .direct
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.as_ref().map(|p| (i, p)))
+            .chain(self.sparse.iter().map(|(&i, p)| (i, p)))
+            .collect::<Vec<_>>();
+
+        pages.sort_by_key(|&(i, _)| i);
+
+        pages
+            .into_iter()
+            .flat_map(|(i, p)| p.iter().enumerate().map(move |(offset, x)| ((i << PAGE_BITS) + offset, x.clone())))
+            .filter(|(_, x)| *x != W::zero())
+            .collect()
+    }
+}
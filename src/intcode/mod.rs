@@ -0,0 +1,714 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+use self::custom::CustomOp;
+use self::history::History;
+pub use self::io::{FnInput, FnOutput, InputSource, IterInput, OutputSink, StdinInput, StdoutOutput};
+use self::memory::Memory;
+use self::profile::Profile;
+use self::trace::{TraceEntry, Tracer};
+use self::watch::{Access, WatchCallback, WatchHit, Watchpoint};
+pub use self::word::{Checked, Word};
+
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod compile;
+pub mod custom;
+pub mod debugger;
+pub mod disasm;
+pub mod fuzz;
+mod history;
+mod io;
+pub mod loader;
+mod memory;
+pub mod network;
+pub mod profile;
+pub mod snapshot;
+pub mod symbolic;
+#[cfg(test)]
+mod tests;
+pub mod threads;
+pub mod trace;
+mod varint;
+pub mod watch;
+mod word;
+
+pub type Program = Vec<i64>;
+
+/// How many instructions `run_with_deadline` executes between looking at the clock.
+const DEADLINE_CHECK_INTERVAL: u64 = 4096;
+
+#[derive(Debug, Copy, Clone)]
+enum Param<W = i64> {
+    Address { x: W },
+    Immediate { x: W },
+    Relative { x: W },
+}
+
+impl<W: Word> Param<W> {
+    fn operand(&self) -> W {
+        match self {
+            Param::Address { x } | Param::Immediate { x } | Param::Relative { x } => x.clone(),
+        }
+    }
+
+    fn mode(&self) -> i64 {
+        match self {
+            Param::Address { .. } => 0,
+            Param::Immediate { .. } => 1,
+            Param::Relative { .. } => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Op<W = i64> {
+    Add { x: Param<W>, y: Param<W>, dst: Param<W> },
+    Mul { x: Param<W>, y: Param<W>, dst: Param<W> },
+    Input { dst: Param<W> },
+    Output { out: Param<W> },
+    CondJmp { cond: bool, x: Param<W>, dst: Param<W> },
+    CmpLess { x: Param<W>, y: Param<W>, dst: Param<W> },
+    CmpEq { x: Param<W>, y: Param<W>, dst: Param<W> },
+    AdjRelBase { x: Param<W> },
+    Halt,
+    /// An instruction registered with `Intcode::register_opcode`.
+    Custom { opcode: i64, name: &'static str, args: Vec<Param<W>>, dst: Option<Param<W>> },
+}
+
+impl<W: Word> Op<W> {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Op::Add { .. } => "ADD",
+            Op::Mul { .. } => "MUL",
+            Op::Input { .. } => "IN",
+            Op::Output { .. } => "OUT",
+            Op::CondJmp { cond: true, .. } => "JNZ",
+            Op::CondJmp { cond: false, .. } => "JZ",
+            Op::CmpLess { .. } => "LT",
+            Op::CmpEq { .. } => "EQ",
+            Op::AdjRelBase { .. } => "ARB",
+            Op::Halt => "HALT",
+            Op::Custom { name, .. } => name,
+        }
+    }
+
+    fn opcode(&self) -> i64 {
+        match self {
+            Op::Add { .. } => 1,
+            Op::Mul { .. } => 2,
+            Op::Input { .. } => 3,
+            Op::Output { .. } => 4,
+            Op::CondJmp { cond: true, .. } => 5,
+            Op::CondJmp { cond: false, .. } => 6,
+            Op::CmpLess { .. } => 7,
+            Op::CmpEq { .. } => 8,
+            Op::AdjRelBase { .. } => 9,
+            Op::Halt => 99,
+            Op::Custom { opcode, .. } => *opcode,
+        }
+    }
+
+    /// The cells this operation is stored as, i.e. the inverse of `decode`.
+    fn encode(&self) -> Vec<W> {
+        let params = self.params();
+        let modes = params.iter().rev().fold(0, |acc, p| acc * 10 + p.mode());
+
+        Some(W::from_i64(modes * 100 + self.opcode())).into_iter().chain(params.iter().map(|p| p.operand())).collect()
+    }
+
+    /// The parameter this operation writes through, if any.
+    fn writes(&self) -> Option<Param<W>> {
+        match self {
+            Op::Add { dst, .. } | Op::Mul { dst, .. } | Op::CmpLess { dst, .. } | Op::CmpEq { dst, .. } | Op::Input { dst } => Some(dst.clone()),
+            Op::Custom { dst, .. } => dst.clone(),
+            _ => None,
+        }
+    }
+
+    fn params(&self) -> Vec<Param<W>> {
+        match self {
+            Op::Add { x, y, dst } | Op::Mul { x, y, dst } | Op::CmpLess { x, y, dst } | Op::CmpEq { x, y, dst } => vec![x.clone(), y.clone(), dst.clone()],
+            Op::Input { dst: x } | Op::Output { out: x } | Op::AdjRelBase { x } => vec![x.clone()],
+            Op::CondJmp { x, dst, .. } => vec![x.clone(), dst.clone()],
+            Op::Halt => vec![],
+            Op::Custom { args, dst, .. } => args.iter().chain(dst).cloned().collect(),
+        }
+    }
+}
+
+/// An operation and the number of cells it occupies.
+type Decoded<W> = (Op<W>, usize);
+
+/// Decodes the instruction at `pc`, reading cells through `read`. Returns the operation and the
+/// number of cells it occupies.
+fn decode<W: Word, F: Fn(usize) -> W>(read: F, pc: usize) -> Result<(Op<W>, usize), IntcodeError> {
+    let word = read(pc);
+    let instruction = word.as_i64().ok_or_else(|| IntcodeError::UnknownOpcode { pc, instruction: word.saturate() })?;
+
+    let num_increments = match instruction % 100 {
+        1 | 2 | 7 | 8 => 4,
+        5 | 6 => 3,
+        3 | 4 | 9 => 2,
+        99 => 1,
+        _ => return Err(IntcodeError::UnknownOpcode { pc, instruction }),
+    };
+
+    let param = |i: u32| decode_param(&read, pc, instruction, i);
+
+    let op = match instruction % 100 {
+        1 => Op::Add { x: param(1)?, y: param(2)?, dst: param(3)? },
+        2 => Op::Mul { x: param(1)?, y: param(2)?, dst: param(3)? },
+        3 => Op::Input { dst: param(1)? },
+        4 => Op::Output { out: param(1)? },
+        5 => Op::CondJmp { cond: true, x: param(1)?, dst: param(2)? },
+        6 => Op::CondJmp { cond: false, x: param(1)?, dst: param(2)? },
+        7 => Op::CmpLess { x: param(1)?, y: param(2)?, dst: param(3)? },
+        8 => Op::CmpEq { x: param(1)?, y: param(2)?, dst: param(3)? },
+        9 => Op::AdjRelBase { x: param(1)? },
+        99 => Op::Halt,
+        _ => unreachable!(),
+    };
+
+    Ok((op, num_increments))
+}
+
+/// Decodes the `i`th parameter, counting from 1, of the instruction at `pc`.
+fn decode_param<W: Word, F: Fn(usize) -> W>(read: &F, pc: usize, instruction: i64, i: u32) -> Result<Param<W>, IntcodeError> {
+    let x = read(pc + i as usize);
+    let mode = 10_i64.checked_pow(i + 1).map_or(0, |m| instruction / m % 10);
+
+    match mode {
+        0 => Ok(Param::Address { x }),
+        1 => Ok(Param::Immediate { x }),
+        2 => Ok(Param::Relative { x }),
+        mode => Err(IntcodeError::InvalidMode { pc, instruction, operand: x.saturate(), mode }),
+    }
+}
+
+/// A fault raised while executing a program. Every variant records the `pc` and the raw
+/// `instruction` it occurred at; the machine is left untouched at that `pc`. Values that don't
+/// fit in an `i64` are clamped to its range.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntcodeError {
+    /// The two lowest digits of the instruction are not a known opcode.
+    UnknownOpcode { pc: usize, instruction: i64 },
+    /// A parameter mode digit is not 0 (position), 1 (immediate) or 2 (relative).
+    InvalidMode { pc: usize, instruction: i64, operand: i64, mode: i64 },
+    /// The instruction tried to write through an immediate mode parameter.
+    ImmediateWrite { pc: usize, instruction: i64, operand: i64 },
+    /// An operand resolved to a negative address, or one too large to represent.
+    InvalidAddress { pc: usize, instruction: i64, operand: i64, address: i64 },
+    /// The result of an arithmetic instruction doesn't fit in the machine's word type.
+    Overflow { pc: usize, instruction: i64 },
+    /// A custom instruction's handler failed.
+    Custom { pc: usize, instruction: i64, message: String },
+    /// An input instruction was reached with no input available.
+    NoInput { pc: usize, instruction: i64 },
+    /// The program halted after producing only part of the requested outputs.
+    PartialOutput { pc: usize, instruction: i64, expected: usize, output: Vec<i64> },
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode { pc, instruction } =>
+                write!(f, "unknown opcode in instruction {} at pc {}", instruction, pc),
+            IntcodeError::InvalidMode { pc, instruction, operand, mode } =>
+                write!(f, "invalid parameter mode {} for operand {} of instruction {} at pc {}", mode, operand, instruction, pc),
+            IntcodeError::ImmediateWrite { pc, instruction, operand } =>
+                write!(f, "write through immediate operand {} of instruction {} at pc {}", operand, instruction, pc),
+            IntcodeError::InvalidAddress { pc, instruction, operand, address } =>
+                write!(f, "invalid address {} from operand {} of instruction {} at pc {}", address, operand, instruction, pc),
+            IntcodeError::Overflow { pc, instruction } =>
+                write!(f, "arithmetic overflow in instruction {} at pc {}", instruction, pc),
+            IntcodeError::Custom { pc, instruction, message } =>
+                write!(f, "{} in instruction {} at pc {}", message, instruction, pc),
+            IntcodeError::NoInput { pc, instruction } =>
+                write!(f, "no input provided for instruction {} at pc {}", instruction, pc),
+            IntcodeError::PartialOutput { pc, instruction, expected, output } =>
+                write!(f, "halted by instruction {} at pc {} after {:?}, expected {} outputs", instruction, pc, output, expected),
+        }
+    }
+}
+
+impl Error for IntcodeError {}
+
+/// Why `run` handed control back to the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunState<W = i64> {
+    Halted,
+    /// Blocked on an input instruction with no input available. Feed `input` and call `run`
+    /// again to resume at that instruction.
+    NeedsInput,
+    /// The program produced a value. It is handed out here instead of being sent to `output`.
+    Output(W),
+    /// The last instruction touched a watched address. An output it produced is handed out by
+    /// the next call to `run`.
+    Watchpoint(Vec<WatchHit>),
+}
+
+/// Why `run_with_limit` or `run_with_deadline` handed control back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetedRun<W = i64> {
+    /// Stopped before the budget ran out, as `run` would have.
+    Stopped(RunState<W>),
+    /// Ran out of instructions or time. Call either again, or `run`, to carry on where it
+    /// stopped.
+    BudgetExhausted,
+}
+
+/// Optional observers attached to a machine. They are not carried over when it is cloned.
+struct Hooks<W> {
+    tracer: Option<Box<dyn Tracer + Send>>,
+    watchpoints: Vec<Watchpoint>,
+    watch_callback: Option<WatchCallback>,
+    watch_hits: Vec<WatchHit>,
+    history: Option<History<W>>,
+    profile: Option<Profile>,
+}
+
+impl<W> Default for Hooks<W> {
+    fn default() -> Self {
+        Hooks { tracer: None, watchpoints: vec![], watch_callback: None, watch_hits: vec![], history: None, profile: None }
+    }
+}
+
+impl<W> Hooks<W> {
+    fn is_active(&self) -> bool {
+        self.tracer.is_some() || !self.watchpoints.is_empty() || self.history.is_some() || self.profile.is_some()
+    }
+}
+
+impl<W> Clone for Hooks<W> {
+    fn clone(&self) -> Self { Hooks::default() }
+}
+
+/// An Intcode machine with memory cells of type `W`. Input instructions read from `input` and
+/// outputs not handed out by `run` go to `output`; both are queues unless the machine is built
+/// with `with_io`.
+#[derive(Clone)]
+pub struct Intcode<W = i64, I = VecDeque<W>, O = VecDeque<W>> {
+    program_len: usize,
+    memory: Memory<W>,
+
+    pc: usize,
+    is_halted: bool,
+    relative_base: i64,
+    /// The number of instructions executed so far.
+    cycles: u64,
+    /// The address of the last instruction executed.
+    last_pc: usize,
+
+    pub input: I,
+    pub output: O,
+
+    pending_output: Option<W>,
+    /// Instructions decoded so far, by address, for the cells of the program image.
+    decode_cache: Option<Vec<Option<Decoded<W>>>>,
+    /// Instructions added with `register_opcode`, by opcode.
+    custom_ops: BTreeMap<i64, CustomOp<W>>,
+    /// The most cells any built-in or registered instruction occupies.
+    max_instruction_len: usize,
+    hooks: Hooks<W>,
+}
+
+impl Intcode {
+    pub fn new(program: &[i64], init_input: Option<&[i64]>) -> Self {
+        let input = match init_input {
+            Some(v) => v.iter().cloned().collect(),
+            None => VecDeque::new(),
+        };
+
+        Intcode::with_io(program, input, VecDeque::new())
+    }
+}
+
+impl<W: Word> Intcode<W> {
+    /// Same as `new`, for machines with another word type. The program and input are converted.
+    pub fn from_program(program: &[i64], init_input: Option<&[i64]>) -> Self {
+        let words = |v: &[i64]| v.iter().map(|&x| W::from_i64(x)).collect::<Vec<_>>();
+        let input = init_input.map(|v| words(v).into_iter().collect()).unwrap_or_default();
+
+        Intcode::with_io(&words(program), input, VecDeque::new())
+    }
+}
+
+impl<W: Word, I: InputSource<W>, O: OutputSink<W>> Intcode<W, I, O> {
+    pub fn with_io(program: &[W], input: I, output: O) -> Self {
+        Intcode {
+            program_len: program.len(),
+            memory: Memory::new(program),
+            pc: 0,
+            is_halted: false,
+            relative_base: 0,
+            cycles: 0,
+            last_pc: 0,
+            input,
+            output,
+            pending_output: None,
+            decode_cache: Some(Vec::new()),
+            custom_ops: BTreeMap::new(),
+            max_instruction_len: 4,
+            hooks: Hooks::default(),
+        }
+    }
+
+    /// The current contents of the cells initially occupied by the program.
+    pub fn program(&self) -> Vec<W> { self.memory.read_range(0, self.program_len) }
+
+    pub fn pc(&self) -> usize { self.pc }
+
+    pub fn relative_base(&self) -> i64 { self.relative_base }
+
+    pub fn is_halted(&self) -> bool { self.is_halted }
+
+    pub fn cycles(&self) -> u64 { self.cycles }
+
+    /// Reads a memory cell from outside the program, e.g. for debuggers.
+    pub fn peek(&self, address: usize) -> W { self.read_mem(address) }
+
+    /// Writes a memory cell from outside the program, e.g. to patch it.
+    pub fn poke(&mut self, address: usize, val: W) { self.write_mem(address, val) }
+
+    /// Turns caching of decoded instructions on or off. It is on by default; writes to memory
+    /// invalidate the cached instructions they overlap.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Records every executed instruction to `tracer` from now on.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer + Send>) { self.hooks.tracer = Some(tracer) }
+
+    /// Detaches the tracer, e.g. to `finish` it.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer + Send>> { self.hooks.tracer.take() }
+
+    /// Executes until the program halts, blocks on input or produces an output.
+    pub fn run(&mut self) -> Result<RunState<W>, IntcodeError> {
+        loop {
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Same as `run`, but gives up with `BudgetedRun::BudgetExhausted` once `max_cycles`
+    /// instructions have been executed.
+    pub fn run_with_limit(&mut self, max_cycles: u64) -> Result<BudgetedRun<W>, IntcodeError> {
+        let limit = self.cycles.saturating_add(max_cycles);
+
+        loop {
+            if self.cycles >= limit && !self.is_halted && self.pending_output.is_none() {
+                return Ok(BudgetedRun::BudgetExhausted);
+            }
+
+            if let Some(state) = self.step()? {
+                return Ok(BudgetedRun::Stopped(state));
+            }
+        }
+    }
+
+    /// Same as `run`, but gives up with `BudgetedRun::BudgetExhausted` once `deadline` has
+    /// passed. The clock is only checked every few thousand instructions.
+    pub fn run_with_deadline(&mut self, deadline: Instant) -> Result<BudgetedRun<W>, IntcodeError> {
+        loop {
+            match self.run_with_limit(DEADLINE_CHECK_INTERVAL)? {
+                BudgetedRun::BudgetExhausted if Instant::now() < deadline => {}
+                run => return Ok(run),
+            }
+        }
+    }
+
+    pub fn run_til_halt(&mut self) -> Result<(), IntcodeError> {
+        while !self.is_halted {
+            self.do_cycle()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn run_til_output(&mut self) -> Result<Option<W>, IntcodeError> {
+        Ok(self.run_til_num_output(1)?.and_then(|mut o| o.pop_front()))
+    }
+
+    /// Returns the next `num` outputs, or `None` if the program halts before producing any.
+    pub fn run_til_num_output(&mut self, num: usize) -> Result<Option<VecDeque<W>>, IntcodeError> {
+        let mut out = VecDeque::with_capacity(num);
+
+        while out.len() < num {
+            match self.run()? {
+                RunState::Halted if out.is_empty() => return Ok(None),
+                // The halt instruction has already advanced the pc past itself
+                RunState::Halted => return Err(IntcodeError::PartialOutput {
+                    pc: self.last_pc,
+                    instruction: self.read_mem(self.last_pc).saturate(),
+                    expected: num,
+                    output: out.iter().map(W::saturate).collect(),
+                }),
+                RunState::NeedsInput => return Err(self.no_input()),
+                RunState::Output(x) => out.push_back(x),
+                RunState::Watchpoint(_) => {}
+            }
+        }
+
+        Ok(Some(out))
+    }
+
+    /// Executes a single instruction. On error the machine stays at the faulting instruction.
+    pub fn do_cycle(&mut self) -> Result<(), IntcodeError> {
+        match self.step()? {
+            Some(RunState::NeedsInput) => return Err(self.no_input()),
+            Some(RunState::Output(x)) => self.emit(x),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Sends an output to `output`, so that stepping back can take it back.
+    pub(super) fn emit(&mut self, x: W) {
+        self.output.emit(x);
+
+        if let Some(history) = self.hooks.history.as_mut() {
+            history.record_output();
+        }
+    }
+
+    //==============================================================================================
+    fn fetch(&mut self) -> Result<(Op<W>, usize), IntcodeError> {
+        let pc = self.pc;
+
+        if let Some(Some(decoded)) = self.decode_cache.as_ref().and_then(|c| c.get(pc)) {
+            return Ok(decoded.clone());
+        }
+
+        let decoded = match decode(|a| self.read_mem(a), pc) {
+            Err(IntcodeError::UnknownOpcode { instruction, .. }) if !self.custom_ops.is_empty() =>
+                self.decode_custom(pc, instruction).unwrap_or(Err(IntcodeError::UnknownOpcode { pc, instruction }))?,
+            decoded => decoded?,
+        };
+
+        match self.decode_cache.as_mut() {
+            Some(cache) if pc < self.program_len => {
+                if cache.len() <= pc {
+                    cache.resize(pc + 1, None);
+                }
+                cache[pc] = Some(decoded.clone());
+            }
+            _ => {}
+        }
+
+        Ok(decoded)
+    }
+
+    fn step(&mut self) -> Result<Option<RunState<W>>, IntcodeError> {
+        if let Some(x) = self.pending_output.take() {
+            return Ok(Some(RunState::Output(x)));
+        }
+
+        if self.is_halted {
+            return Ok(Some(RunState::Halted));
+        }
+
+        let (pc, (op, num_increments)) = (self.pc, self.fetch()?);
+
+        let entry = self.hooks.tracer.as_ref().map(|_| TraceEntry::before(self, &op));
+
+        if let Some(mut history) = self.hooks.history.take() {
+            history.begin(self);
+            self.hooks.history = Some(history);
+        }
+
+        let out = match self.execute(&op, num_increments) {
+            Ok(out) => out,
+            Err(e) => {
+                self.hooks.watch_hits.clear();
+                if let Some(history) = self.hooks.history.as_mut() {
+                    history.abort();
+                }
+                return match e {
+                    IntcodeError::NoInput { .. } => Ok(Some(RunState::NeedsInput)),
+                    e => Err(e),
+                };
+            }
+        };
+
+        self.cycles += 1;
+        self.last_pc = pc;
+
+        if let Some(profile) = self.hooks.profile.as_mut() {
+            profile.record(pc, &op, self.cycles);
+        }
+
+        if let Some(entry) = entry {
+            let entry = entry.after(self);
+            self.hooks.tracer.as_mut().unwrap().record(&entry);
+        }
+
+        if !self.hooks.watch_hits.is_empty() {
+            self.pending_output = out;
+            return Ok(Some(RunState::Watchpoint(self.hooks.watch_hits.drain(..).collect())));
+        }
+
+        // A custom instruction can output and halt at once; the output is handed out first
+        Ok(match (self.is_halted, out) {
+            (_, Some(x)) => Some(RunState::Output(x)),
+            (true, None) => Some(RunState::Halted),
+            _ => None,
+        })
+    }
+
+    fn execute(&mut self, op: &Op<W>, num_increments: usize) -> Result<Option<W>, IntcodeError> {
+        let mut next_pc = self.pc + num_increments;
+        let mut out = None;
+
+        match op {
+            Op::Add { x, y, dst } => self.binary_op(x, y, dst, W::try_add)?,
+            Op::Mul { x, y, dst } => self.binary_op(x, y, dst, W::try_mul)?,
+            Op::Input { dst } => {
+                // Resolve first so that a bad destination doesn't consume the input
+                self.resolve(dst)?;
+                let x = self.input.next_input().ok_or_else(|| self.no_input())?;
+
+                if let Some(history) = self.hooks.history.as_mut() {
+                    history.record_input(x.clone());
+                }
+
+                self.write(dst, x)?;
+            }
+            Op::Output { out: x } => out = Some(self.read(x)?),
+            Op::CondJmp { cond, x, dst } => if (self.read(x)? != W::zero()) == *cond { next_pc = self.jump_target(dst)? },
+            Op::CmpLess { x, y, dst } => self.binary_op(x, y, dst, |x, y| Some(W::from_i64(if x < y { 1 } else { 0 })))?,
+            Op::CmpEq { x, y, dst } => self.binary_op(x, y, dst, |x, y| Some(W::from_i64(if x == y { 1 } else { 0 })))?,
+            Op::AdjRelBase { x } => {
+                let offset = self.read(x)?.as_i64().ok_or_else(|| self.overflow())?;
+                self.relative_base = self.relative_base.checked_add(offset).ok_or_else(|| self.overflow())?;
+            }
+            Op::Halt => self.is_halted = true,
+            Op::Custom { opcode, args, dst, .. } => {
+                let (output, jump) = self.execute_custom(*opcode, args, dst.as_ref())?;
+                out = output;
+                next_pc = jump.unwrap_or(next_pc);
+            }
+        };
+
+        self.pc = next_pc;
+        Ok(out)
+    }
+
+    fn binary_op(&mut self, x: &Param<W>, y: &Param<W>, dst: &Param<W>, op: fn(&W, &W) -> Option<W>) -> Result<(), IntcodeError> {
+        let val = op(&self.read(x)?, &self.read(y)?).ok_or_else(|| self.overflow())?;
+        self.write(dst, val)
+    }
+
+    fn read_mem(&self, address: usize) -> W {
+        self.memory.get(address)
+    }
+
+    fn write_mem(&mut self, address: usize, val: W) {
+        self.memory.set(address, val);
+
+        // Drop every cached instruction that could cover the address
+        if let Some(cache) = self.decode_cache.as_mut() {
+            let end = cache.len().min(address.saturating_add(1));
+            let start = address.saturating_sub(self.max_instruction_len - 1).min(end);
+            cache[start..end].iter_mut().for_each(|d| *d = None);
+        }
+    }
+
+    /// The instruction at the pc, for error reports.
+    fn instruction(&self) -> i64 {
+        self.read_mem(self.pc).saturate()
+    }
+
+    fn no_input(&self) -> IntcodeError {
+        IntcodeError::NoInput { pc: self.pc, instruction: self.instruction() }
+    }
+
+    fn overflow(&self) -> IntcodeError {
+        IntcodeError::Overflow { pc: self.pc, instruction: self.instruction() }
+    }
+
+    fn invalid_address(&self, x: &Param<W>, address: i64) -> IntcodeError {
+        IntcodeError::InvalidAddress { pc: self.pc, instruction: self.instruction(), operand: x.operand().saturate(), address }
+    }
+
+    /// Turns an operand into an address. `address` is `None` if it isn't representable.
+    fn check_address(&self, x: &Param<W>, address: Option<i64>) -> Result<usize, IntcodeError> {
+        match address {
+            Some(a) if a >= 0 => Ok(a as usize),
+            Some(a) => Err(self.invalid_address(x, a)),
+            None => Err(self.invalid_address(x, x.operand().saturate())),
+        }
+    }
+
+    fn resolve(&self, x: &Param<W>) -> Result<usize, IntcodeError> {
+        match x {
+            Param::Address { x: a } => self.check_address(x, a.as_i64()),
+            Param::Immediate { x: a } =>
+                Err(IntcodeError::ImmediateWrite { pc: self.pc, instruction: self.instruction(), operand: a.saturate() }),
+            Param::Relative { x: a } => self.check_address(x, a.as_i64().and_then(|a| self.relative_base.checked_add(a))),
+        }
+    }
+
+    fn jump_target(&mut self, dst: &Param<W>) -> Result<usize, IntcodeError> {
+        let target = self.read(dst)?;
+        self.check_address(dst, target.as_i64())
+    }
+
+    /// The value of a parameter, without notifying watchpoints.
+    fn value(&self, x: &Param<W>) -> Result<W, IntcodeError> {
+        match x {
+            Param::Immediate { x } => Ok(x.clone()),
+            _ => Ok(self.read_mem(self.resolve(x)?)),
+        }
+    }
+
+    fn read(&mut self, x: &Param<W>) -> Result<W, IntcodeError> {
+        let val = self.value(x)?;
+
+        if !self.hooks.watchpoints.is_empty() {
+            if let Ok(address) = self.resolve(x) {
+                self.watch(address, Access::Read, &val);
+            }
+        }
+
+        Ok(val)
+    }
+
+    fn write(&mut self, dst: &Param<W>, val: W) -> Result<(), IntcodeError> {
+        let address = self.resolve(dst)?;
+
+        if let Some(history) = self.hooks.history.as_mut() {
+            history.record_write(address, self.memory.get(address));
+        }
+
+        if self.hooks.watchpoints.is_empty() {
+            self.write_mem(address, val);
+        } else {
+            self.write_mem(address, val.clone());
+            self.watch(address, Access::Write, &val);
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: fmt::Debug, I: fmt::Debug, O: fmt::Debug> fmt::Debug for Intcode<W, I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<pc: {}, is_halted: {}, relative_base: {}, input: {:?}, output: {:?}", self.pc, self.is_halted, self.relative_base, self.input, self.output)
+    }
+}
+
+/// Yields outputs until the machine halts. A fault also ends the iteration; use
+/// `run_til_output` directly to observe the `IntcodeError`.
+impl<W: Word, I: InputSource<W>, O: OutputSink<W>> Iterator for Intcode<W, I, O> {
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        self.run_til_output().ok().flatten()
+    }
+}
@@ -0,0 +1,131 @@
+use super::{Intcode, IntcodeError, RunState};
+
+/// The address of the NAT, which watches the network and wakes it up when it goes idle.
+pub const NAT_ADDRESS: i64 = 255;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Packet {
+    pub src: i64,
+    pub dest: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// Something that happened on the network during a `tick`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NetworkEvent {
+    /// A machine sent a packet. Packets to addresses without a machine are dropped, unless they
+    /// go to the NAT.
+    Sent(Packet),
+    /// Every machine was waiting for input and no packets were sent.
+    Idle,
+    /// The NAT resent the last packet it received to address 0.
+    NatResent(Packet),
+}
+
+/// A number of machines running the same program, each booted with its network address as its
+/// first input, that talk to each other with (destination, x, y) output packets. A machine
+/// reading from an empty queue receives -1.
+///
+/// Scheduling is deterministic: each tick runs the machines in address order, each until it
+/// blocks on input, and packets are delivered as soon as they are sent.
+pub struct Network {
+    machines: Vec<Intcode>,
+    /// Outputs of each machine that don't form a complete packet yet.
+    partial: Vec<Vec<i64>>,
+    nat: Option<Option<Packet>>,
+}
+
+impl Network {
+    pub fn new(program: &[i64], size: usize) -> Self {
+        Network {
+            machines: (0..size as i64).map(|a| Intcode::new(program, Some(&[a]))).collect(),
+            partial: vec![vec![]; size],
+            nat: None,
+        }
+    }
+
+    /// Attaches a NAT at `NAT_ADDRESS`. It keeps the last packet sent to it and resends it to
+    /// address 0 whenever the network is idle.
+    pub fn enable_nat(&mut self) {
+        self.nat.get_or_insert(None);
+    }
+
+    pub fn machine(&self, address: usize) -> &Intcode { &self.machines[address] }
+
+    pub fn machine_mut(&mut self, address: usize) -> &mut Intcode { &mut self.machines[address] }
+
+    /// Gives every machine that hasn't halted a turn, and returns what happened.
+    pub fn tick(&mut self) -> Result<Vec<NetworkEvent>, IntcodeError> {
+        let mut events = vec![];
+        let mut idle = true;
+
+        for src in 0..self.machines.len() {
+            if self.machines[src].is_halted() {
+                continue;
+            }
+
+            if self.machines[src].input.is_empty() {
+                self.machines[src].input.push_back(-1);
+            } else {
+                idle = false;
+            }
+
+            loop {
+                match self.machines[src].run()? {
+                    RunState::Output(x) => {
+                        self.partial[src].push(x);
+
+                        if let [dest, x, y] = self.partial[src][..] {
+                            self.partial[src].clear();
+                            let packet = Packet { src: src as i64, dest, x, y };
+                            self.deliver(packet);
+                            events.push(NetworkEvent::Sent(packet));
+                            idle = false;
+                        }
+                    }
+                    RunState::Watchpoint(_) => {}
+                    RunState::Halted | RunState::NeedsInput => break,
+                }
+            }
+        }
+
+        if idle {
+            events.push(NetworkEvent::Idle);
+
+            if let Some(Some(packet)) = self.nat {
+                let packet = Packet { src: NAT_ADDRESS, dest: 0, ..packet };
+                self.deliver(packet);
+                events.push(NetworkEvent::NatResent(packet));
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Ticks until `f` picks a result out of an event. Returns `None` if every machine halts
+    /// first, since nothing can happen after that.
+    pub fn run_until<T, F: FnMut(&NetworkEvent) -> Option<T>>(&mut self, mut f: F) -> Result<Option<T>, IntcodeError> {
+        loop {
+            if let Some(result) = self.tick()?.iter().find_map(&mut f) {
+                return Ok(Some(result));
+            }
+
+            if self.machines.iter().all(Intcode::is_halted) {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn deliver(&mut self, packet: Packet) {
+        match (packet.dest, self.nat.as_mut()) {
+            (NAT_ADDRESS, Some(nat)) => *nat = Some(packet),
+            (dest, _) if dest >= 0 && (dest as usize) < self.machines.len() => {
+                let input = &mut self.machines[dest as usize].input;
+                input.push_back(packet.x);
+                input.push_back(packet.y);
+            }
+            _ => {}
+        }
+    }
+}
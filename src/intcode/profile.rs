@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use super::{InputSource, Intcode, Op, OutputSink, Word};
+
+/// Execution counts and I/O timings gathered while a machine runs with profiling enabled.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Executions of the instruction at each address that has run, kept sparse like memory.
+    counts: HashMap<usize, u64>,
+    /// Executions of each kind of instruction, by mnemonic.
+    opcodes: BTreeMap<&'static str, u64>,
+    /// Wall-clock time between consecutive input or output instructions.
+    io_gaps: Vec<Duration>,
+    /// Instructions executed between consecutive outputs, including the output itself.
+    output_cycles: Vec<u64>,
+    last_io: Instant,
+    last_output: u64,
+}
+
+impl Profile {
+    fn new(cycles: u64) -> Self {
+        Profile {
+            counts: HashMap::new(),
+            opcodes: BTreeMap::new(),
+            io_gaps: vec![],
+            output_cycles: vec![],
+            last_io: Instant::now(),
+            last_output: cycles,
+        }
+    }
+
+    /// Counts an instruction that has just executed, `cycles` being the machine's total so far.
+    pub(super) fn record<W: Word>(&mut self, pc: usize, op: &Op<W>, cycles: u64) {
+        *self.counts.entry(pc).or_insert(0) += 1;
+        *self.opcodes.entry(op.mnemonic()).or_insert(0) += 1;
+
+        match op {
+            Op::Input { .. } | Op::Output { .. } => {
+                let now = Instant::now();
+                self.io_gaps.push(now - self.last_io);
+                self.last_io = now;
+            }
+            _ => {}
+        }
+
+        if let Op::Output { .. } = op {
+            self.output_cycles.push(cycles - self.last_output);
+            self.last_output = cycles;
+        }
+    }
+
+    /// The number of executions of the instruction at `address`.
+    pub fn count(&self, address: usize) -> u64 { self.counts.get(&address).cloned().unwrap_or(0) }
+
+    pub fn opcode_counts(&self) -> &BTreeMap<&'static str, u64> { &self.opcodes }
+
+    pub fn io_gaps(&self) -> &[Duration] { &self.io_gaps }
+
+    pub fn cycles_per_output(&self) -> &[u64] { &self.output_cycles }
+
+    /// The `n` most executed addresses with their counts, hottest first.
+    pub fn hottest(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut hot = self.counts.iter().map(|(&a, &c)| (a, c)).collect::<Vec<_>>();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(n);
+        hot
+    }
+
+    /// A summary followed by the `top` hottest addresses, each with the instruction `render`
+    /// finds there.
+    pub fn report<F: Fn(usize) -> String>(&self, render: F, top: usize) -> String {
+        let total = self.opcodes.values().sum::<u64>();
+        let percent = |c: u64| if total == 0 { 0.0 } else { 100.0 * c as f64 / total as f64 };
+        let mut out = String::new();
+
+        writeln!(out, "instructions: {}", total).unwrap();
+
+        if let (Some(min), Some(max)) = (self.output_cycles.iter().min(), self.output_cycles.iter().max()) {
+            let mean = self.output_cycles.iter().sum::<u64>() / self.output_cycles.len() as u64;
+            writeln!(out, "outputs: {}, cycles per output: {} mean, {} min, {} max", self.output_cycles.len(), mean, min, max).unwrap();
+        }
+
+        if let Some(max) = self.io_gaps.iter().max() {
+            let mean = self.io_gaps.iter().sum::<Duration>() / self.io_gaps.len() as u32;
+            writeln!(out, "I/O events: {}, time between: {:?} mean, {:?} max", self.io_gaps.len(), mean, max).unwrap();
+        }
+
+        writeln!(out, "\nby opcode:").unwrap();
+        let mut opcodes = self.opcodes.iter().collect::<Vec<_>>();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (mnemonic, &count) in opcodes {
+            writeln!(out, "  {:<5}{:>12} {:>6.2}%", mnemonic, count, percent(count)).unwrap();
+        }
+
+        writeln!(out, "\nhottest addresses:").unwrap();
+        for (address, count) in self.hottest(top) {
+            writeln!(out, "  {:>12} {:>6.2}% {:>6}  {}", count, percent(count), address, render(address)).unwrap();
+        }
+
+        out
+    }
+}
+
+impl<W: Word, I: InputSource<W>, O: OutputSink<W>> Intcode<W, I, O> {
+    /// Starts counting executed instructions and timing I/O. Machines being profiled are always
+    /// interpreted, even by a `CompiledMachine`.
+    pub fn enable_profiler(&mut self) {
+        let cycles = self.cycles;
+        self.hooks.profile.get_or_insert_with(|| Profile::new(cycles));
+    }
+
+    pub fn profile(&self) -> Option<&Profile> { self.hooks.profile.as_ref() }
+
+    /// Stops profiling and hands over what was gathered.
+    pub fn take_profile(&mut self) -> Option<Profile> { self.hooks.profile.take() }
+
+    /// The profile's report, with instructions read from the machine's current memory.
+    pub fn profile_report(&self, top: usize) -> Option<String> {
+        let render = |a: usize| self.instruction_at(a).map(|(t, _)| t).unwrap_or_else(|| format!("DATA {}", self.read_mem(a).saturate()));
+        self.profile().map(|p| p.report(render, top))
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::memory::Memory;
+use super::varint::{read_varint, write_varint};
+use super::Intcode;
+
+const MAGIC: &[u8; 6] = b"ICSNAP";
+const VERSION: i64 = 2;
+
+/// Longer than any real program by far, but short enough that copying out the program image of a
+/// loaded snapshot can't exhaust memory.
+const MAX_PROGRAM_LEN: usize = 1 << 24;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The data doesn't start with the snapshot magic bytes.
+    NotASnapshot,
+    UnsupportedVersion(i64),
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::NotASnapshot => write!(f, "not an Intcode snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            SnapshotError::Corrupt(what) => write!(f, "corrupt snapshot: {}", what),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self { SnapshotError::Io(e) }
+}
+
+fn write_queue<W: Write>(out: &mut W, queue: &VecDeque<i64>) -> io::Result<()> {
+    write_varint(out, queue.len() as i64)?;
+    queue.iter().try_for_each(|&x| write_varint(out, x))
+}
+
+fn read_count<R: Read>(input: &mut R, what: &'static str) -> Result<usize, SnapshotError> {
+    match read_varint(input)? {
+        n if n < 0 => Err(SnapshotError::Corrupt(what)),
+        n => Ok(n as usize),
+    }
+}
+
+fn read_queue<R: Read>(input: &mut R) -> Result<VecDeque<i64>, SnapshotError> {
+    let len = read_count(input, "negative queue length")?;
+    (0..len).map(|_| Ok(read_varint(input)?)).collect()
+}
+
+impl Intcode {
+    /// Writes the complete machine state: memory, registers, I/O queues and any output held back
+    /// by a watchpoint. Tracers and watchpoints are not part of the state.
+    ///
+    /// The format is the magic bytes `ICSNAP` followed by zigzag varints: version, program
+    /// length, pc, relative base, halted flag, cycle count, held back output flag [and value],
+    /// input queue and output queue (each a length and the values), then the number of non-zero
+    /// memory cells and for each the gap to the previous cell's address and its value.
+    pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+
+        for &x in &[VERSION, self.program_len as i64, self.pc as i64, self.relative_base, self.is_halted as i64, self.cycles as i64] {
+            write_varint(&mut out, x)?;
+        }
+
+        match self.pending_output {
+            Some(x) => {
+                write_varint(&mut out, 1)?;
+                write_varint(&mut out, x)?;
+            }
+            None => write_varint(&mut out, 0)?,
+        }
+
+        write_queue(&mut out, &self.input)?;
+        write_queue(&mut out, &self.output)?;
+
+        let cells = self.memory.cells();
+        write_varint(&mut out, cells.len() as i64)?;
+
+        let mut last = 0;
+        for (address, x) in cells {
+            write_varint(&mut out, (address - last) as i64)?;
+            write_varint(&mut out, x)?;
+            last = address;
+        }
+
+        out.flush()
+    }
+
+    /// Restores a machine written by `save`. Snapshots get passed around, so anything a
+    /// well-formed one can't contain is rejected as `SnapshotError::Corrupt`.
+    pub fn load<R: Read>(mut input: R) -> Result<Intcode, SnapshotError> {
+        let mut magic = [0; 6];
+        input.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let version = match read_varint(&mut input)? {
+            v @ 1..=VERSION => v,
+            v => return Err(SnapshotError::UnsupportedVersion(v)),
+        };
+
+        let program_len = match read_count(&mut input, "negative program length")? {
+            n if n > MAX_PROGRAM_LEN => return Err(SnapshotError::Corrupt("program too long")),
+            n => n,
+        };
+        let pc = read_count(&mut input, "negative pc")?;
+        let relative_base = read_varint(&mut input)?;
+        let is_halted = read_varint(&mut input)? != 0;
+        // Version 1 snapshots predate the cycle counter
+        let cycles = if version >= 2 { read_varint(&mut input)? as u64 } else { 0 };
+        let pending_output = match read_varint(&mut input)? {
+            0 => None,
+            _ => Some(read_varint(&mut input)?),
+        };
+
+        let mut machine = Intcode::new(&[], None);
+        machine.input = read_queue(&mut input)?;
+        machine.output = read_queue(&mut input)?;
+
+        let mut memory = Memory::default();
+        let mut address = 0_usize;
+        for _ in 0..read_count(&mut input, "negative cell count")? {
+            let gap = read_count(&mut input, "negative address gap")?;
+            address = match address.checked_add(gap) {
+                Some(a) if a <= i64::MAX as usize => a,
+                _ => return Err(SnapshotError::Corrupt("address out of range")),
+            };
+            memory.set(address, read_varint(&mut input)?);
+        }
+
+        machine.program_len = program_len;
+        machine.memory = memory;
+        machine.pc = pc;
+        machine.relative_base = relative_base;
+        machine.is_halted = is_halted;
+        machine.cycles = cycles;
+        machine.pending_output = pending_output;
+
+        Ok(machine)
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::{decode, IntcodeError, Op};
+
+/// An unknown value a `SymbolicMachine` computes with, numbered in the order it was introduced.
+pub type Symbol = usize;
+
+/// A value of the form `constant + c0 * s0 + c1 * s1 + ...`. Terms with a zero coefficient are
+/// never stored, so a value is constant exactly when it has no terms.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Linear {
+    constant: i64,
+    terms: BTreeMap<Symbol, i64>,
+}
+
+impl Linear {
+    pub fn constant(x: i64) -> Self { Linear { constant: x, terms: BTreeMap::new() } }
+
+    pub fn symbol(s: Symbol) -> Self { Linear { constant: 0, terms: Some((s, 1)).into_iter().collect() } }
+
+    /// The value, if it doesn't depend on any symbol.
+    pub fn as_const(&self) -> Option<i64> {
+        if self.terms.is_empty() { Some(self.constant) } else { None }
+    }
+
+    pub fn coefficient(&self, s: Symbol) -> i64 { self.terms.get(&s).cloned().unwrap_or(0) }
+
+    /// The value with each symbol replaced by `values[symbol]`, or `None` on overflow.
+    pub fn eval(&self, values: &[i64]) -> Option<i64> {
+        self.terms.iter().try_fold(self.constant, |acc, (&s, &c)| acc.checked_add(c.checked_mul(values[s])?))
+    }
+
+    /// `None` on overflow.
+    fn checked_add(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+
+        for (&s, &c) in &other.terms {
+            let coefficient = sum.coefficient(s).checked_add(c)?;
+            if coefficient == 0 {
+                sum.terms.remove(&s);
+            } else {
+                sum.terms.insert(s, coefficient);
+            }
+        }
+
+        Some(sum)
+    }
+
+    /// `None` on overflow.
+    fn checked_scale(&self, k: i64) -> Option<Linear> {
+        let terms = match k {
+            0 => BTreeMap::new(),
+            k => self.terms.iter().map(|(&s, &c)| Some((s, c.checked_mul(k)?))).collect::<Option<_>>()?,
+        };
+
+        Some(Linear { constant: self.constant.checked_mul(k)?, terms })
+    }
+
+    /// `Err` with a description if the product isn't linear, `Ok(None)` on overflow.
+    fn checked_mul(&self, other: &Linear) -> Result<Option<Linear>, &'static str> {
+        match (self.as_const(), other.as_const()) {
+            (_, Some(k)) => Ok(self.checked_scale(k)),
+            (Some(k), _) => Ok(other.checked_scale(k)),
+            _ => Err("product of two symbolic values"),
+        }
+    }
+
+    /// `self - other`, if it is constant.
+    fn const_difference(&self, other: &Linear) -> Option<i64> {
+        self.checked_add(&other.checked_scale(-1)?)?.as_const()
+    }
+}
+
+impl From<i64> for Linear {
+    fn from(x: i64) -> Self { Linear::constant(x) }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+
+        for (&s, &c) in &self.terms {
+            let sign = match (first, c < 0) {
+                (true, true) => "-",
+                (true, false) => "",
+                (false, true) => " - ",
+                (false, false) => " + ",
+            };
+            match c.unsigned_abs() {
+                1 => write!(f, "{}s{}", sign, s)?,
+                k => write!(f, "{}{}*s{}", sign, k, s)?,
+            }
+            first = false;
+        }
+
+        match (first, self.constant) {
+            (true, x) => write!(f, "{}", x),
+            (false, 0) => Ok(()),
+            (false, x) if x < 0 => write!(f, " - {}", x.unsigned_abs()),
+            (false, x) => write!(f, " + {}", x),
+        }
+    }
+}
+
+/// The contents of a cell in a `SymbolicMachine`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Linear(Linear),
+    /// A value outside the supported fragment, e.g. the product of two symbolic values or a
+    /// cell read through a symbolic address. It only makes the program unsupported once it is
+    /// needed, so it may be computed and then overwritten or ignored.
+    Opaque { pc: usize, reason: &'static str },
+}
+
+impl Value {
+    pub fn as_const(&self) -> Option<i64> {
+        match self {
+            Value::Linear(x) => x.as_const(),
+            Value::Opaque { .. } => None,
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self { Value::Linear(Linear::default()) }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Linear(x) => write!(f, "{}", x),
+            Value::Opaque { pc, reason } => write!(f, "<{} at pc {}>", reason, pc),
+        }
+    }
+}
+
+/// Why a program couldn't be executed symbolically.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolicError {
+    /// The program faulted in a way that doesn't depend on the symbols.
+    Fault(IntcodeError),
+    /// The program left the supported fragment. Instructions, jump conditions and targets,
+    /// relative base adjustments and the addresses written to must all be concrete, and values
+    /// solved for must be linear in the symbols.
+    Unsupported { pc: usize, instruction: i64, reason: &'static str },
+    /// Solving for the symbols took numbers too large even for `i128`.
+    Overflow,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Fault(e) => write!(f, "{}", e),
+            SymbolicError::Unsupported { pc, instruction, reason } =>
+                write!(f, "unsupported: {} in instruction {} at pc {}", reason, instruction, pc),
+            SymbolicError::Overflow => write!(f, "overflow while solving"),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+impl From<IntcodeError> for SymbolicError {
+    fn from(e: IntcodeError) -> Self { SymbolicError::Fault(e) }
+}
+
+/// A machine whose memory cells and inputs may hold symbols as well as numbers, for solving for
+/// the values that make a program produce a given result.
+///
+/// Symbols are introduced with `symbolic_cell` and `push_symbolic_input`, each with the range of
+/// values it may take. Running the program then computes every cell and output as a `Value`
+/// over the symbols, and `solve` finds values for the symbols that give a linear one a target
+/// value. Comparisons are exact as long as their outcome doesn't depend on the symbols.
+#[derive(Debug, Clone)]
+pub struct SymbolicMachine {
+    /// Cells that have been written to; the rest hold 0.
+    memory: BTreeMap<usize, Value>,
+    pc: usize,
+    relative_base: i64,
+    is_halted: bool,
+    input: VecDeque<Value>,
+    outputs: Vec<Value>,
+    ranges: Vec<RangeInclusive<i64>>,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[i64]) -> Self {
+        SymbolicMachine {
+            memory: program.iter().map(|&x| Value::Linear(Linear::constant(x))).enumerate().collect(),
+            pc: 0,
+            relative_base: 0,
+            is_halted: false,
+            input: VecDeque::new(),
+            outputs: vec![],
+            ranges: vec![],
+        }
+    }
+
+    /// Replaces the cell at `address` with a new symbol taking values in `range`.
+    pub fn symbolic_cell(&mut self, address: usize, range: RangeInclusive<i64>) -> Symbol {
+        let s = self.new_symbol(range);
+        self.write(address, Value::Linear(Linear::symbol(s)));
+        s
+    }
+
+    pub fn push_input(&mut self, x: i64) { self.input.push_back(Value::Linear(Linear::constant(x))); }
+
+    /// Queues a new symbol taking values in `range` as the next input.
+    pub fn push_symbolic_input(&mut self, range: RangeInclusive<i64>) -> Symbol {
+        let s = self.new_symbol(range);
+        self.input.push_back(Value::Linear(Linear::symbol(s)));
+        s
+    }
+
+    pub fn peek(&self, address: usize) -> Value { self.memory.get(&address).cloned().unwrap_or_default() }
+
+    pub fn outputs(&self) -> &[Value] { &self.outputs }
+
+    pub fn is_halted(&self) -> bool { self.is_halted }
+
+    /// Runs the program until it halts.
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        while !self.is_halted {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Values for all symbols, in the order they were introduced, that make `value` equal
+    /// `target`, or `None` if there are none within the symbols' ranges. The first solution in
+    /// lexicographic order is returned.
+    ///
+    /// Every symbol but the last two is searched for, so only those should have large ranges.
+    pub fn solve(&self, value: &Value, target: i64) -> Result<Option<Vec<i64>>, SymbolicError> {
+        let expr = match value {
+            Value::Linear(expr) => expr,
+            &Value::Opaque { pc, reason } =>
+                return Err(SymbolicError::Unsupported { pc, instruction: self.instruction_at(pc), reason }),
+        };
+
+        let coefficients = (0..self.ranges.len()).map(|s| expr.coefficient(s) as i128).collect::<Vec<_>>();
+        let ranges = self.ranges.iter().map(|r| (*r.start() as i128, *r.end() as i128)).collect::<Vec<_>>();
+
+        let solution = solve_linear(&coefficients, &ranges, target as i128 - expr.constant as i128).ok_or(SymbolicError::Overflow)?;
+        Ok(solution.map(|xs| xs.into_iter().map(|x| x as i64).collect()))
+    }
+
+    fn new_symbol(&mut self, range: RangeInclusive<i64>) -> Symbol {
+        self.ranges.push(range);
+        self.ranges.len() - 1
+    }
+
+    fn write(&mut self, address: usize, value: Value) {
+        self.memory.insert(address, value);
+    }
+
+    fn instruction_at(&self, pc: usize) -> i64 { self.peek(pc).as_const().unwrap_or(0) }
+
+    fn instruction(&self) -> i64 { self.instruction_at(self.pc) }
+
+    fn unsupported(&self, reason: &'static str) -> SymbolicError {
+        SymbolicError::Unsupported { pc: self.pc, instruction: self.instruction(), reason }
+    }
+
+    fn opaque(&self, reason: &'static str) -> Value { Value::Opaque { pc: self.pc, reason } }
+
+    fn overflow(&self) -> SymbolicError {
+        IntcodeError::Overflow { pc: self.pc, instruction: self.instruction() }.into()
+    }
+
+    /// A value that has to be concrete, failing with `reason` if it isn't.
+    fn concrete(&self, x: &Value, reason: &'static str) -> Result<i64, SymbolicError> {
+        x.as_const().ok_or_else(|| self.unsupported(reason))
+    }
+
+    /// The address a parameter refers to, or `None` if it depends on the symbols.
+    fn address(&self, mode: i64, operand: &Value) -> Result<Option<usize>, SymbolicError> {
+        let x = match operand.as_const() {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let address = match mode {
+            2 => self.relative_base.checked_add(x),
+            _ => Some(x),
+        };
+
+        match address {
+            Some(a) if a >= 0 => Ok(Some(a as usize)),
+            a => Err(IntcodeError::InvalidAddress { pc: self.pc, instruction: self.instruction(), operand: x, address: a.unwrap_or(x) }.into()),
+        }
+    }
+
+    fn read(&self, (mode, operand): &(i64, Value)) -> Result<Value, SymbolicError> {
+        if *mode == 1 {
+            return Ok(operand.clone());
+        }
+
+        match self.address(*mode, operand)? {
+            Some(a) => Ok(self.peek(a)),
+            None => Ok(self.opaque("read through a symbolic address")),
+        }
+    }
+
+    fn resolve(&self, (mode, operand): &(i64, Value)) -> Result<usize, SymbolicError> {
+        if *mode == 1 {
+            let operand = operand.as_const().unwrap_or(0);
+            return Err(IntcodeError::ImmediateWrite { pc: self.pc, instruction: self.instruction(), operand }.into());
+        }
+
+        self.address(*mode, operand)?.ok_or_else(|| self.unsupported("write through a symbolic address"))
+    }
+
+    /// Applies a linear operation, or passes on the first opaque operand.
+    fn combine<F>(&self, x: Value, y: Value, f: F) -> Result<Value, SymbolicError>
+        where F: Fn(&Linear, &Linear) -> Result<Option<Linear>, &'static str> {
+        match (x, y) {
+            (Value::Linear(x), Value::Linear(y)) => match f(&x, &y) {
+                Ok(Some(z)) => Ok(Value::Linear(z)),
+                Ok(None) => Err(self.overflow()),
+                Err(reason) => Ok(self.opaque(reason)),
+            },
+            (opaque @ Value::Opaque { .. }, _) | (_, opaque) => Ok(opaque),
+        }
+    }
+
+    fn step(&mut self) -> Result<(), SymbolicError> {
+        let instruction = self.concrete(&self.peek(self.pc), "symbolic instruction")?;
+
+        // Decode with the interpreter's decoder, then take the operands from memory, since they
+        // may be symbolic.
+        let pc = self.pc;
+        let (op, num_increments) = decode(|a| self.peek(a).as_const().unwrap_or(0), pc)?;
+        let params = op.params().iter().enumerate().map(|(i, p)| (p.mode(), self.peek(pc + 1 + i))).collect::<Vec<_>>();
+
+        let mut next_pc = pc + num_increments;
+
+        match op {
+            Op::Add { .. } => {
+                let sum = self.combine(self.read(&params[0])?, self.read(&params[1])?, |x, y| Ok(x.checked_add(y)))?;
+                self.write(self.resolve(&params[2])?, sum);
+            }
+            Op::Mul { .. } => {
+                let product = self.combine(self.read(&params[0])?, self.read(&params[1])?, Linear::checked_mul)?;
+                self.write(self.resolve(&params[2])?, product);
+            }
+            Op::CmpLess { .. } | Op::CmpEq { .. } => {
+                let is_less = matches!(op, Op::CmpLess { .. });
+                let result = self.combine(self.read(&params[0])?, self.read(&params[1])?, |x, y| {
+                    let difference = x.const_difference(y).ok_or("comparison that depends on a symbol")?;
+                    let result = if is_less { difference < 0 } else { difference == 0 };
+                    Ok(Some(Linear::constant(result as i64)))
+                })?;
+                self.write(self.resolve(&params[2])?, result);
+            }
+            Op::Input { .. } => {
+                let dst = self.resolve(&params[0])?;
+                let x = self.input.pop_front().ok_or(IntcodeError::NoInput { pc, instruction })?;
+                self.write(dst, x);
+            }
+            Op::Output { .. } => {
+                let x = self.read(&params[0])?;
+                self.outputs.push(x);
+            }
+            Op::CondJmp { cond, .. } => {
+                let x = self.concrete(&self.read(&params[0])?, "branch on a symbolic condition")?;
+                if (x != 0) == cond {
+                    let target = self.concrete(&self.read(&params[1])?, "jump to a symbolic address")?;
+                    if target < 0 {
+                        return Err(IntcodeError::InvalidAddress { pc, instruction, operand: target, address: target }.into());
+                    }
+                    next_pc = target as usize;
+                }
+            }
+            Op::AdjRelBase { .. } => {
+                let x = self.concrete(&self.read(&params[0])?, "symbolic relative base adjustment")?;
+                self.relative_base = self.relative_base.checked_add(x).ok_or_else(|| self.overflow())?;
+            }
+            Op::Halt => self.is_halted = true,
+            Op::Custom { .. } => unreachable!(),
+        }
+
+        if !self.is_halted {
+            self.pc = next_pc;
+        }
+
+        Ok(())
+    }
+}
+
+/// `None` on overflow.
+fn div_floor(a: i128, b: i128) -> Option<i128> {
+    let q = a.checked_div(b)?;
+    Some(if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q })
+}
+
+/// `None` on overflow.
+fn div_ceil(a: i128, b: i128) -> Option<i128> { div_floor(a.checked_neg()?, b)?.checked_neg() }
+
+/// Returns `(g, x, y)` with `a * x + b * y == g == gcd(a, b)`, or `None` on overflow.
+fn extended_gcd(a: i128, b: i128) -> Option<(i128, i128, i128)> {
+    if b == 0 {
+        Some((a.checked_abs()?, a.signum(), 0))
+    } else {
+        let (g, x, y) = extended_gcd(b, a.checked_rem(b)?)?;
+        Some((g, y, x.checked_sub((a / b).checked_mul(y)?)?))
+    }
+}
+
+/// The lexicographically smallest `xs` with `ranges[i].0 <= xs[i] <= ranges[i].1` and
+/// `sum(coefficients[i] * xs[i]) == target`, or `Some(None)` if there is none. `None` on
+/// overflow.
+fn solve_linear(coefficients: &[i128], ranges: &[(i128, i128)], target: i128) -> Option<Option<Vec<i128>>> {
+    Some(match (coefficients, ranges) {
+        ([], []) if target == 0 => Some(vec![]),
+        ([], []) => None,
+        (&[a], &[(lo, hi)]) => match a {
+            0 if target == 0 && lo <= hi => Some(vec![lo]),
+            0 => None,
+            a if target.checked_rem(a)? == 0 && lo <= target / a && target / a <= hi => Some(vec![target / a]),
+            _ => None,
+        },
+        (&[a, b], &[(lo_x, hi_x), (lo_y, hi_y)]) if a != 0 && b != 0 => {
+            let (g, x0, _) = extended_gcd(a, b)?;
+            if target.checked_rem(g)? != 0 {
+                return Some(None);
+            }
+
+            // All solutions are x = x0 + k * dx, y = y0 - k * dy. Scaling x0 by target / g can
+            // overflow, but only x0 modulo dx matters.
+            let (dx, dy) = (b / g, a / g);
+            let x0 = (x0 % dx).checked_mul((target / g) % dx)? % dx;
+            let y0 = target.checked_sub(a.checked_mul(x0)?)? / b;
+            let bounds = |lo: i128, hi: i128, base: i128, step: i128| if step > 0 {
+                Some((div_ceil(lo.checked_sub(base)?, step)?, div_floor(hi.checked_sub(base)?, step)?))
+            } else {
+                Some((div_ceil(hi.checked_sub(base)?, step)?, div_floor(lo.checked_sub(base)?, step)?))
+            };
+            let (k_lo_x, k_hi_x) = bounds(lo_x, hi_x, x0, dx)?;
+            let (k_lo_y, k_hi_y) = bounds(lo_y, hi_y, y0, dy.checked_neg()?)?;
+            let (k_lo, k_hi) = (k_lo_x.max(k_lo_y), k_hi_x.min(k_hi_y));
+            if k_lo > k_hi {
+                return Some(None);
+            }
+
+            let k = if dx > 0 { k_lo } else { k_hi };
+            Some(vec![x0.checked_add(k.checked_mul(dx)?)?, y0.checked_sub(k.checked_mul(dy)?)?])
+        }
+        _ => {
+            let (lo, hi) = ranges[0];
+            for x in lo..=hi {
+                let rest = target.checked_sub(coefficients[0].checked_mul(x)?)?;
+                if let Some(mut xs) = solve_linear(&coefficients[1..], &ranges[1..], rest)? {
+                    xs.insert(0, x);
+                    return Some(Some(xs));
+                }
+            }
+
+            None
+        }
+    })
+}
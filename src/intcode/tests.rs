@@ -0,0 +1,1270 @@
+use super::fuzz::{self, Arithmetic, Case, End, Outcome};
+use super::*;
+
+const MAX_CYCLES: u64 = 100_000;
+
+/// Runs `program` on `input` through the reference interpreter and every path in `fuzz::PATHS`,
+/// checks that they all agree, and returns the outcome with `i64` arithmetic.
+fn run(program: &[i64], input: &[i64]) -> Outcome {
+    let case = Case { program: program.to_vec(), input: input.to_vec() };
+
+    if let Err(mismatch) = fuzz::check(&case, MAX_CYCLES) {
+        panic!("{}", mismatch);
+    }
+
+    fuzz::reference(&case, Arithmetic::Wrapping, MAX_CYCLES)
+}
+
+/// The first `len` cells of memory after a run.
+fn image(outcome: &Outcome, len: usize) -> Vec<i64> {
+    let mut cells = vec![0; len];
+    outcome.memory.iter().filter(|&&(a, _)| a < len).for_each(|&(a, x)| cells[a] = x);
+    cells
+}
+
+/// The memory image of a program run to completion without input.
+fn halted_image(program: &[i64]) -> Vec<i64> {
+    let outcome = run(program, &[]);
+    assert_eq!(outcome.end, End::Halted);
+    image(&outcome, program.len())
+}
+
+/// The outputs of a program run to completion.
+fn outputs(program: &[i64], input: &[i64]) -> Vec<i64> {
+    let outcome = run(program, input);
+    assert_eq!(outcome.end, End::Halted);
+    outcome.output
+}
+
+fn fault(program: &[i64], input: &[i64]) -> IntcodeError {
+    match run(program, input).end {
+        End::Fault(e) => e,
+        end => panic!("expected a fault, got {:?}", end),
+    }
+}
+
+mod day02 {
+    use super::*;
+
+    #[test]
+    fn add_and_multiply() {
+        assert_eq!(halted_image(&[1, 0, 0, 0, 99]), [2, 0, 0, 0, 99]);
+        assert_eq!(halted_image(&[2, 3, 0, 3, 99]), [2, 3, 0, 6, 99]);
+        assert_eq!(halted_image(&[2, 4, 4, 5, 99, 0]), [2, 4, 4, 5, 99, 9801]);
+        assert_eq!(halted_image(&[1, 1, 1, 4, 99, 5, 6, 0, 99]), [30, 1, 1, 4, 2, 5, 6, 0, 99]);
+        assert_eq!(halted_image(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]), [3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]);
+    }
+
+    #[test]
+    fn halt_ignores_what_follows() {
+        let outcome = run(&[99, 1, 0, 0, 0, 12345], &[]);
+        assert_eq!(outcome.end, End::Halted);
+        assert_eq!(outcome.cycles, 1);
+        assert_eq!(image(&outcome, 6), [99, 1, 0, 0, 0, 12345]);
+    }
+
+    #[test]
+    fn memory_past_the_program_reads_zero_and_grows_on_write() {
+        let outcome = run(&[1, 1000, 0, 2000, 1, 2000, 4, 3000, 99], &[]);
+        assert_eq!(outcome.end, End::Halted);
+        assert_eq!(outcome.memory.iter().filter(|&&(a, _)| a >= 9).collect::<Vec<_>>(), [&(2000, 1), &(3000, 2)]);
+    }
+
+    #[test]
+    fn unknown_opcode_faults_at_its_pc() {
+        assert_eq!(fault(&[1, 0, 0, 0, 42], &[]), IntcodeError::UnknownOpcode { pc: 4, instruction: 42 });
+        assert_eq!(fault(&[0], &[]), IntcodeError::UnknownOpcode { pc: 0, instruction: 0 });
+        assert_eq!(fault(&[-1], &[]), IntcodeError::UnknownOpcode { pc: 0, instruction: -1 });
+    }
+
+    #[test]
+    fn running_off_the_end_faults() {
+        assert_eq!(fault(&[1, 0, 0, 0], &[]), IntcodeError::UnknownOpcode { pc: 4, instruction: 0 });
+    }
+
+    #[test]
+    fn negative_address_faults() {
+        assert_eq!(
+            fault(&[1, -1, 0, 0, 99], &[]),
+            IntcodeError::InvalidAddress { pc: 0, instruction: 1, operand: -1, address: -1 },
+        );
+    }
+}
+
+mod day05 {
+    use super::*;
+
+    #[test]
+    fn input_and_output() {
+        assert_eq!(outputs(&[3, 0, 4, 0, 99], &[1234]), [1234]);
+        assert_eq!(outputs(&[3, 0, 4, 0, 99], &[-7]), [-7]);
+    }
+
+    #[test]
+    fn parameter_modes_apply_per_parameter() {
+        assert_eq!(halted_image(&[1002, 4, 3, 4, 33]), [1002, 4, 3, 4, 99]);
+        assert_eq!(halted_image(&[1101, 100, -1, 4, 0]), [1101, 100, -1, 4, 99]);
+        assert_eq!(halted_image(&[101, 10, 5, 5, 99, 7]), [101, 10, 5, 5, 99, 17]);
+        assert_eq!(outputs(&[104, -5, 4, 0, 99], &[]), [-5, 104]);
+    }
+
+    #[test]
+    fn comparisons() {
+        // Equal to 8, less than 8, in position and immediate mode
+        let programs = [
+            (vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], [0, 1, 0]),
+            (vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], [1, 0, 0]),
+            (vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], [0, 1, 0]),
+            (vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], [1, 0, 0]),
+        ];
+
+        for (program, expected) in &programs {
+            for (&input, &expected) in [7, 8, 9].iter().zip(expected) {
+                assert_eq!(outputs(program, &[input]), [expected], "{:?} on {}", program, input);
+            }
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        let programs = [
+            vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+        ];
+
+        for program in &programs {
+            assert_eq!(outputs(program, &[0]), [0]);
+            assert_eq!(outputs(program, &[8]), [1]);
+            assert_eq!(outputs(program, &[-8]), [1], "negative values are non-zero");
+        }
+    }
+
+    #[test]
+    fn compare_to_eight() {
+        let program = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125,
+            20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+        ];
+
+        assert_eq!(outputs(&program, &[-3]), [999]);
+        assert_eq!(outputs(&program, &[8]), [1000]);
+        assert_eq!(outputs(&program, &[1001]), [1001]);
+    }
+
+    #[test]
+    fn jump_to_negative_address_faults() {
+        assert_eq!(
+            fault(&[1105, 1, -4], &[]),
+            IntcodeError::InvalidAddress { pc: 0, instruction: 1105, operand: -4, address: -4 },
+        );
+    }
+
+    #[test]
+    fn write_through_immediate_faults() {
+        assert_eq!(fault(&[11101, 1, 1, 0, 99], &[]), IntcodeError::ImmediateWrite { pc: 0, instruction: 11101, operand: 0 });
+        assert_eq!(fault(&[103, 0, 99], &[5]), IntcodeError::ImmediateWrite { pc: 0, instruction: 103, operand: 0 });
+    }
+
+    #[test]
+    fn invalid_mode_faults() {
+        assert_eq!(fault(&[301, 0, 0, 0, 99], &[]), IntcodeError::InvalidMode { pc: 0, instruction: 301, operand: 0, mode: 3 });
+        assert_eq!(fault(&[30001, 0, 0, 7, 99], &[]), IntcodeError::InvalidMode { pc: 0, instruction: 30001, operand: 7, mode: 3 });
+    }
+}
+
+mod day07 {
+    use super::*;
+
+    use super::compile::CompiledProgram;
+    use super::threads::Topology;
+
+    fn thrust(program: &[i64], phases: &[i64]) -> i64 {
+        phases.iter().fold(0, |signal, &phase| outputs(program, &[phase, signal])[0])
+    }
+
+    /// Runs the amplifiers round robin on one thread, each until it blocks on input.
+    fn feedback(program: &[i64], phases: &[i64]) -> i64 {
+        let mut amps = phases.iter().map(|&p| Intcode::new(program, Some(&[p]))).collect::<Vec<_>>();
+        let mut signal = 0;
+
+        loop {
+            for amp in &mut amps {
+                amp.input.push_back(signal);
+                loop {
+                    match amp.run().unwrap() {
+                        RunState::Output(x) => signal = x,
+                        RunState::NeedsInput | RunState::Halted => break,
+                        state => panic!("unexpected {:?}", state),
+                    }
+                }
+            }
+
+            if amps.iter().all(|a| a.is_halted()) {
+                return signal;
+            }
+        }
+    }
+
+    const FEEDBACK: [i64; 29] = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+    ];
+
+    #[test]
+    fn amplifier_chain() {
+        let program = [3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+        assert_eq!(thrust(&program, &[4, 3, 2, 1, 0]), 43210);
+
+        let program = [3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0];
+        assert_eq!(thrust(&program, &[0, 1, 2, 3, 4]), 54321);
+    }
+
+    #[test]
+    fn blocking_on_input_keeps_state() {
+        assert_eq!(feedback(&FEEDBACK, &[9, 8, 7, 6, 5]), 139629729);
+    }
+
+    #[test]
+    fn needs_input_doesnt_execute_the_instruction() {
+        let outcome = run(&[3, 5, 4, 5, 99, 0], &[]);
+        assert_eq!(outcome.end, End::NeedsInput);
+        assert_eq!((outcome.pc, outcome.cycles), (0, 0));
+
+        let mut machine = Intcode::new(&[3, 5, 4, 5, 99, 0], None);
+        assert_eq!(machine.run(), Ok(RunState::NeedsInput));
+        assert_eq!(machine.run(), Ok(RunState::NeedsInput));
+        machine.input.push_back(17);
+        assert_eq!(machine.run(), Ok(RunState::Output(17)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn compiled_machines_block_and_resume() {
+        let compiled = CompiledProgram::new(&FEEDBACK);
+        let mut machine = compiled.instantiate(Some(&[5]));
+        assert_eq!(machine.run(), Ok(RunState::NeedsInput));
+        machine.input().push_back(0);
+        assert_eq!(machine.run(), Ok(RunState::Output(1)));
+    }
+
+    #[test]
+    fn threaded_feedback_loop() {
+        let mut topology = Topology::new();
+        let amps = [9, 7, 8, 5, 6].iter().map(|&p| topology.add(&[
+            3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54, -5, 54, 1105, 1, 12, 1,
+            53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4, 53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0,
+            0, 10,
+        ], &[p])).collect::<Vec<_>>();
+
+        for (&from, &to) in amps.iter().zip(amps.iter().cycle().skip(1)) {
+            topology.connect(from, to);
+        }
+
+        let thrust = topology.tap(amps[4]);
+        topology.feed(amps[0]).send(0).unwrap();
+        topology.spawn().join().unwrap();
+
+        assert_eq!(thrust.try_iter().last(), Some(18216));
+    }
+
+    #[test]
+    fn threads_stop_once_any_machine_halts() {
+        let mut topology = Topology::new();
+        // Outputs 1 forever without reading
+        let producer = topology.add(&[104, 1, 1105, 1, 0], &[]);
+        // Echoes one value and halts
+        let echo = topology.add(&[3, 5, 4, 5, 99, 0], &[]);
+        // Reads forever from a sender that is never dropped
+        let reader = topology.add(&[3, 5, 1105, 1, 0, 0], &[7]);
+        topology.connect(producer, echo);
+
+        let echoed = topology.tap(echo);
+        let _feed = topology.feed(reader);
+        topology.spawn().join().unwrap();
+
+        assert_eq!(echoed.try_iter().collect::<Vec<_>>(), [1]);
+    }
+}
+
+mod day09 {
+    use super::*;
+
+    #[test]
+    fn quine() {
+        let program = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        assert_eq!(outputs(&program, &[]), program);
+    }
+
+    #[test]
+    fn large_numbers() {
+        assert_eq!(outputs(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]), [1219070632396864]);
+        assert_eq!(outputs(&[104, 1125899906842624, 99], &[]), [1125899906842624]);
+    }
+
+    #[test]
+    fn relative_mode_reads_and_writes() {
+        // rb = 10; mem[rb + 2] = input; mem[rb + 3] = mem[rb + 2] + mem[rb - 5]; out mem[rb + 3]
+        let program = [109, 10, 203, 2, 22201, 2, -5, 3, 204, 3, 99];
+        let outcome = run(&program, &[7]);
+        assert_eq!(outcome.end, End::Halted);
+        assert_eq!(outcome.output, [7 + 2]);
+        assert_eq!(outcome.relative_base, 10);
+    }
+
+    #[test]
+    fn relative_base_accumulates() {
+        let outcome = run(&[109, 5, 109, -2, 209, 0, 99], &[]);
+        assert_eq!(outcome.end, End::Halted);
+        // The last adjustment reads mem[3] = -2 through the relative base
+        assert_eq!(outcome.relative_base, 1);
+    }
+
+    #[test]
+    fn negative_relative_address_faults() {
+        assert_eq!(
+            fault(&[109, -3, 204, 1, 99], &[]),
+            IntcodeError::InvalidAddress { pc: 2, instruction: 204, operand: 1, address: -2 },
+        );
+    }
+
+    #[test]
+    fn relative_base_overflow_faults() {
+        assert_eq!(
+            fault(&[109, i64::MAX, 22101, 0, 1, 0, 99], &[]),
+            IntcodeError::InvalidAddress { pc: 2, instruction: 22101, operand: 1, address: 1 },
+        );
+        assert_eq!(fault(&[109, i64::MAX, 109, 1, 99], &[]), IntcodeError::Overflow { pc: 2, instruction: 109 });
+    }
+
+    #[test]
+    fn wide_words() {
+        use num::bigint::BigInt;
+
+        let program = [1102, 34915192, 34915192, 11, 1002, 11, 34915192, 11, 4, 11, 99, 0];
+        let mut machine = Intcode::<BigInt>::from_program(&program, None);
+        let x = BigInt::from(34915192_i64);
+        assert_eq!(machine.run(), Ok(RunState::Output(&x * &x * &x)));
+
+        let mut machine = Intcode::<Checked>::from_program(&[1102, i64::MAX, 2, 0, 99], None);
+        assert_eq!(machine.run(), Err(IntcodeError::Overflow { pc: 0, instruction: 1102 }));
+
+        let mut machine = Intcode::<i128>::from_program(&[1102, i64::MAX, i64::MAX, 0, 1002, 0, 4, 0, 99], None);
+        assert_eq!(machine.run(), Err(IntcodeError::Overflow { pc: 4, instruction: 1002 }));
+    }
+
+    #[test]
+    fn plain_words_wrap() {
+        let program = [1102, i64::MAX, 2, 9, 1101, i64::MAX, 1, 10, 99];
+        let mut machine = Intcode::new(&program, None);
+        machine.run_til_halt().unwrap();
+        assert_eq!((machine.peek(9), machine.peek(10)), (-2, i64::MIN));
+
+        let compiled = compile::CompiledProgram::new(&program);
+        let mut machine = compiled.instantiate(None);
+        machine.run_til_halt().unwrap();
+        assert_eq!((machine.machine().peek(9), machine.machine().peek(10)), (-2, i64::MIN));
+    }
+}
+
+mod self_modification {
+    use super::*;
+
+    #[test]
+    fn patched_operand() {
+        // Writes 42 into the operand of the following output instruction
+        assert_eq!(outputs(&[1101, 0, 42, 5, 104, 0, 99], &[]), [42]);
+    }
+
+    #[test]
+    fn patched_opcode() {
+        assert_eq!(halted_image(&[1101, 100, -1, 4, 0]), [1101, 100, -1, 4, 99]);
+        assert_eq!(outputs(&[1101, 3, 1, 4, 0, 6, 99], &[]), [99]);
+    }
+
+    #[test]
+    fn code_rewritten_in_a_loop() {
+        // Bumps the operand of the output instruction every time round
+        let program = [104, 0, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99];
+        assert_eq!(outputs(&program, &[]), [0, 1, 2]);
+    }
+
+    #[test]
+    fn patch_after_compilation() {
+        let compiled = compile::CompiledProgram::new(&[104, 1, 99]);
+        let mut machine = compiled.instantiate(None);
+        machine.poke(1, 2);
+        assert_eq!(machine.run(), Ok(RunState::Output(2)));
+    }
+
+    #[test]
+    fn poke_at_the_last_address() {
+        let mut machine = Intcode::new(&[104, 1, 99], None);
+        machine.poke(usize::MAX, 5);
+        assert_eq!(machine.peek(usize::MAX), 5);
+        assert_eq!(machine.run(), Ok(RunState::Output(1)));
+
+        let compiled = compile::CompiledProgram::new(&[104, 1, 99]);
+        let mut machine = compiled.instantiate(None);
+        machine.poke(usize::MAX, 5);
+        assert_eq!(machine.run(), Ok(RunState::Output(1)));
+    }
+
+    #[test]
+    fn patched_operand_of_a_long_custom_instruction() {
+        use super::custom::{CustomEffect, CustomOp};
+        use std::sync::Arc;
+
+        // SUM4 1, 1, 1, 1; then write 100 over its last operand and run it again
+        let program = [111142, 1, 1, 1, 1, 1101, 0, 100, 4, 1105, 1, 0];
+
+        for &cached in &[false, true] {
+            let mut machine = Intcode::new(&program, None);
+            machine.set_decode_cache(cached);
+            machine.register_opcode(42, CustomOp {
+                name: "SUM4",
+                arity: 4,
+                writes: false,
+                handler: Arc::new(|c| Ok(CustomEffect { output: Some(c.args.iter().sum()), ..Default::default() })),
+            });
+
+            assert_eq!(machine.run(), Ok(RunState::Output(4)));
+            assert_eq!(machine.run(), Ok(RunState::Output(103)), "with the cache {}", if cached { "on" } else { "off" });
+        }
+    }
+}
+
+mod halting {
+    use super::*;
+
+    #[test]
+    fn halted_machine_stays_halted() {
+        let mut machine = Intcode::new(&[104, 1, 99, 104, 2], None);
+        assert_eq!(machine.run(), Ok(RunState::Output(1)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        assert!(machine.is_halted());
+        assert_eq!(machine.cycles(), 2);
+    }
+
+    #[test]
+    fn halt_advances_the_pc() {
+        let outcome = run(&[1, 0, 0, 0, 99], &[]);
+        assert_eq!((outcome.pc, outcome.cycles), (5, 2));
+    }
+
+    #[test]
+    fn budget() {
+        let outcome = run(&[1105, 1, 0], &[]);
+        assert_eq!(outcome.end, End::OutOfCycles);
+
+        let mut machine = Intcode::new(&[1105, 1, 0], None);
+        assert_eq!(machine.run_with_limit(10), Ok(BudgetedRun::BudgetExhausted));
+        assert_eq!(machine.cycles(), 10);
+    }
+
+    #[test]
+    fn partial_output() {
+        let mut machine = Intcode::new(&[104, 1, 99], None);
+        assert_eq!(
+            machine.run_til_num_output(2),
+            Err(IntcodeError::PartialOutput { pc: 2, instruction: 99, expected: 2, output: vec![1] }),
+        );
+
+        let mut machine = Intcode::new(&[99], None);
+        assert_eq!(machine.run_til_num_output(2), Ok(None));
+    }
+
+    #[test]
+    fn custom_instructions_that_halt() {
+        use super::custom::{CustomEffect, CustomOp};
+        use std::sync::Arc;
+
+        // OUTHALT #7, which is shown in listings like a built-in instruction
+        let mut machine = Intcode::new(&[10142, 7, 0], None);
+        machine.register_opcode(42, CustomOp {
+            name: "OUTHALT",
+            arity: 1,
+            writes: false,
+            handler: Arc::new(|c| Ok(CustomEffect { output: Some(c.args[0]), halt: true, ..Default::default() })),
+        });
+
+        assert_eq!(machine.instruction_at(0), Some(("OUTHALT #7".to_string(), 2)));
+        assert_eq!(machine.instruction_at(2), None);
+        assert_eq!(
+            machine.run_til_num_output(2),
+            Err(IntcodeError::PartialOutput { pc: 0, instruction: 10142, expected: 2, output: vec![7] }),
+        );
+    }
+}
+
+mod watchpoints {
+    use super::*;
+
+    use std::ops::RangeInclusive;
+    use std::sync::{Arc, Mutex};
+
+    use super::watch::{Access, WatchHit, WatchKind};
+
+    /// Adds one to cell 9 into cell 10, and outputs cell 10.
+    const PROGRAM: [i64; 11] = [1001, 9, 1, 10, 4, 10, 99, 0, 0, 41, 0];
+
+    fn hit(pc: usize, address: usize, access: Access, value: i64) -> WatchHit {
+        WatchHit { pc, instruction: PROGRAM[pc], address, access, value }
+    }
+
+    fn watched(range: RangeInclusive<usize>, kind: WatchKind) -> Intcode {
+        let mut machine = Intcode::new(&PROGRAM, None);
+        machine.add_watchpoint(range, kind);
+        machine
+    }
+
+    #[test]
+    fn write_hits() {
+        let mut machine = watched(10..=10, WatchKind::Write);
+        assert_eq!(machine.run(), Ok(RunState::Watchpoint(vec![hit(0, 10, Access::Write, 42)])));
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn read_hits_hold_back_the_output() {
+        let mut machine = watched(10..=10, WatchKind::Read);
+        assert_eq!(machine.run(), Ok(RunState::Watchpoint(vec![hit(4, 10, Access::Read, 42)])));
+        assert_eq!(machine.pc(), 6);
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn read_write_hits() {
+        let mut machine = watched(9..=10, WatchKind::ReadWrite);
+        assert_eq!(machine.run(), Ok(RunState::Watchpoint(vec![hit(0, 9, Access::Read, 41), hit(0, 10, Access::Write, 42)])));
+        assert_eq!(machine.run(), Ok(RunState::Watchpoint(vec![hit(4, 10, Access::Read, 42)])));
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+    }
+
+    #[test]
+    fn callback_instead_of_pausing() {
+        let hits = Arc::new(Mutex::new(vec![]));
+        let mut machine = watched(9..=10, WatchKind::ReadWrite);
+        let sink = Arc::clone(&hits);
+        machine.set_watch_callback(Box::new(move |h| sink.lock().unwrap().push(h.clone())));
+
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        assert_eq!(*hits.lock().unwrap(), [hit(0, 9, Access::Read, 41), hit(0, 10, Access::Write, 42), hit(4, 10, Access::Read, 42)]);
+    }
+
+    #[test]
+    fn removing_watchpoints() {
+        let mut machine = watched(9..=10, WatchKind::ReadWrite);
+        machine.add_watchpoint(20..=30, WatchKind::Write);
+        assert!(machine.remove_watchpoints(10));
+        assert!(!machine.remove_watchpoints(10));
+        assert_eq!(machine.watchpoints().len(), 1);
+
+        assert_eq!(machine.run(), Ok(RunState::Output(42)));
+    }
+}
+
+mod tracing {
+    use super::*;
+
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use super::trace::{TraceEntry, TraceFormat, TraceWriter, Tracer};
+
+    /// Adds two numbers into a cell past the relative base, and outputs it.
+    const PROGRAM: [i64; 9] = [109, 10, 21101, 2, 3, 1, 204, 1, 99];
+
+    /// A tracer or writer that can still be looked at once the machine owns it.
+    #[derive(Clone, Default)]
+    struct Shared<T>(Arc<Mutex<T>>);
+
+    impl Tracer for Shared<Vec<TraceEntry>> {
+        fn record(&mut self, entry: &TraceEntry) { self.0.lock().unwrap().record(entry) }
+    }
+
+    impl Write for Shared<Vec<u8>> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.lock().unwrap().write(buf) }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    fn entry(pc: usize, op: &str, operands: &[i64], write: Option<(usize, i64)>) -> TraceEntry {
+        TraceEntry { pc, instruction: PROGRAM[pc], op: op.to_string(), operands: operands.to_vec(), write, relative_base: 10 }
+    }
+
+    fn traced(format: TraceFormat) -> Vec<u8> {
+        let out = Shared::default();
+        let mut machine = Intcode::new(&PROGRAM, None);
+        machine.set_tracer(Box::new(TraceWriter::new(out.clone(), format)));
+        machine.run_til_halt().unwrap();
+        machine.take_tracer().unwrap().finish().unwrap();
+
+        let bytes = out.0.lock().unwrap().clone();
+        bytes
+    }
+
+    #[test]
+    fn entries() {
+        let entries = Shared::default();
+        let mut machine = Intcode::new(&PROGRAM, None);
+        machine.set_tracer(Box::new(entries.clone()));
+        machine.run_til_halt().unwrap();
+
+        assert_eq!(machine.output, [5]);
+        assert_eq!(*entries.0.lock().unwrap(), [
+            entry(0, "ARB  #10", &[10], None),
+            entry(2, "ADD  #2, #3, %1", &[2, 3, 11], Some((11, 5))),
+            entry(6, "OUT  %1", &[5], None),
+            entry(8, "HALT", &[], None),
+        ]);
+    }
+
+    #[test]
+    fn json_lines() {
+        assert_eq!(String::from_utf8(traced(TraceFormat::JsonLines)).unwrap(), "\
+{\"n\":0,\"pc\":0,\"instruction\":109,\"op\":\"ARB  #10\",\"operands\":[10],\"write\":null,\"relative_base\":10}
+{\"n\":1,\"pc\":2,\"instruction\":21101,\"op\":\"ADD  #2, #3, %1\",\"operands\":[2,3,11],\"write\":[11,5],\"relative_base\":10}
+{\"n\":2,\"pc\":6,\"instruction\":204,\"op\":\"OUT  %1\",\"operands\":[5],\"write\":null,\"relative_base\":10}
+{\"n\":3,\"pc\":8,\"instruction\":99,\"op\":\"HALT\",\"operands\":[],\"write\":null,\"relative_base\":10}
+");
+    }
+
+    #[test]
+    fn binary() {
+        let mut expected = b"ICTRACE1".to_vec();
+        expected.extend_from_slice(&[
+            // pc, instruction, operand count, operands, write flag [address, value], relative base
+            0, 0xda, 0x01, 2, 20, 0, 20,
+            4, 0xda, 0xc9, 0x02, 6, 4, 6, 22, 2, 22, 10, 20,
+            12, 0x98, 0x03, 2, 10, 0, 20,
+            16, 0xc6, 0x01, 0, 0, 20,
+        ]);
+        assert_eq!(traced(TraceFormat::Binary), expected);
+    }
+}
+
+mod profiling {
+    use super::*;
+
+    #[test]
+    fn counts_instructions_far_out_in_memory() {
+        let far = 1 << 40;
+        let mut machine = Intcode::new(&[1101, 99, 0, far, 1106, 0, far], None);
+        machine.enable_profiler();
+        machine.run_til_halt().unwrap();
+
+        let profile = machine.profile().unwrap();
+        assert_eq!((profile.count(0), profile.count(far as usize), profile.count(1)), (1, 1, 0));
+        assert_eq!(profile.hottest(2), [(0, 1), (4, 1)]);
+        assert_eq!(profile.opcode_counts().values().sum::<u64>(), 3);
+        assert!(machine.profile_report(3).unwrap().ends_with(&format!("{}  HALT\n", far)));
+    }
+}
+
+mod io {
+    use super::*;
+
+    #[test]
+    fn outputs_are_handed_out_one_at_a_time() {
+        let mut machine = Intcode::new(&[104, 1, 104, 2, 99], None);
+        assert_eq!(machine.run(), Ok(RunState::Output(1)));
+        assert_eq!(machine.run(), Ok(RunState::Output(2)));
+        assert_eq!(machine.run(), Ok(RunState::Halted));
+        assert!(machine.output.is_empty());
+    }
+
+    #[test]
+    fn run_til_halt_collects_outputs() {
+        let mut machine = Intcode::new(&[3, 0, 4, 0, 3, 0, 4, 0, 99], Some(&[5, 6, 7]));
+        machine.run_til_halt().unwrap();
+        assert_eq!(machine.output, [5, 6]);
+        assert_eq!(machine.input, [7], "unread input is left queued");
+    }
+
+    #[test]
+    fn missing_input_is_an_error_when_running_to_completion() {
+        let mut machine = Intcode::new(&[3, 0, 99], None);
+        assert_eq!(machine.run_til_halt(), Err(IntcodeError::NoInput { pc: 0, instruction: 3 }));
+        assert_eq!(machine.pc(), 0);
+    }
+
+    #[test]
+    fn bad_destination_doesnt_consume_input() {
+        let mut machine = Intcode::new(&[203, -1, 99], Some(&[5]));
+        assert!(machine.run().is_err());
+        assert_eq!(machine.input, [5]);
+    }
+
+    #[test]
+    fn custom_sources_and_sinks() {
+        let mut seen = vec![];
+        let mut machine = Intcode::with_io(&[3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0], IterInput(1..=3_i64), FnOutput(|x| seen.push(x)));
+        assert_eq!(machine.run_til_halt(), Err(IntcodeError::NoInput { pc: 0, instruction: 3 }));
+        drop(machine);
+        assert_eq!(seen, [2, 4, 6]);
+    }
+}
+
+mod ascii_io {
+    use super::*;
+
+    use super::ascii::{AsciiDecoder, AsciiOutput};
+
+    /// Prints a line and a prompt, reads a character, then prints it on its own line followed by
+    /// a value outside the ASCII range.
+    const PROGRAM: [i64; 20] = [104, 104, 104, 105, 104, 10, 104, 62, 104, 32, 3, 19, 4, 19, 104, 10, 104, 1000, 99, 0];
+
+    fn line(s: &str) -> AsciiOutput { AsciiOutput::Line(s.to_string()) }
+
+    #[test]
+    fn decoder() {
+        let mut decoder = AsciiDecoder::new();
+        assert_eq!(decoder.push(97), None);
+        assert_eq!(decoder.push(98), None);
+
+        // Values that aren't ASCII come through right away, leaving the line buffered
+        assert_eq!(decoder.push(128), Some(AsciiOutput::Value(128)));
+        assert_eq!(decoder.push(-1), Some(AsciiOutput::Value(-1)));
+        assert_eq!(decoder.push(10), Some(line("ab")));
+        assert_eq!(decoder.push(10), Some(line("")));
+
+        assert_eq!(decoder.flush(), None);
+        decoder.push(62);
+        assert_eq!(decoder.flush(), Some(line(">")));
+        assert_eq!(decoder.flush(), None);
+    }
+
+    #[test]
+    fn run_ascii() {
+        let mut machine = Intcode::new(&PROGRAM, None);
+        assert_eq!(machine.run_ascii().unwrap(), (vec![line("hi"), line("> ")], RunState::NeedsInput));
+
+        machine.push_line("yes");
+        assert_eq!(machine.run_ascii().unwrap(), (vec![line("y"), AsciiOutput::Value(1000)], RunState::Halted));
+        assert_eq!(machine.input, [101, 115, 10]);
+    }
+
+    #[test]
+    fn interact() {
+        let mut out = vec![];
+        let mut machine = Intcode::new(&PROGRAM, None);
+        machine.interact(&b"yes\n"[..], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "hi\n> \ny\n[1000]\n");
+        assert!(machine.is_halted());
+
+        // Running out of input ends the session early
+        let mut out = vec![];
+        let mut machine = Intcode::new(&PROGRAM, None);
+        machine.interact(&b""[..], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "hi\n> \n");
+        assert!(!machine.is_halted());
+    }
+}
+
+mod assembly {
+    use super::*;
+
+    use super::asm::{assemble, AsmError};
+
+    fn error(source: &str) -> (usize, String) {
+        match assemble(source) {
+            Err(AsmError { line, message }) => (line, message),
+            Ok(program) => panic!("expected an error, got {:?}", program),
+        }
+    }
+
+    #[test]
+    fn labels_modes_and_data() {
+        let source = "\
+            ; doubles its input
+                  IN    @x
+                  MUL   @x, #2, @x
+                  OUT   %x
+                  JNZ   #1, #end+1
+                  DATA  -3
+            end:  DATA  0
+                  HALT
+            x:    DATA  0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program, [3, 14, 1002, 14, 2, 14, 204, 14, 1105, 1, 13, -3, 0, 99, 0]);
+        assert_eq!(outputs(&program, &[21]), [42]);
+    }
+
+    #[test]
+    fn listings_assemble_back() {
+        let quine = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        assert_eq!(assemble(&disasm::listing(&quine)).unwrap(), quine);
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        assert_eq!(error("HALT\nFOO @1"), (2, "unknown mnemonic 'FOO'".to_string()));
+        assert_eq!(error("\n\nADD @1, #2"), (3, "ADD takes 3 operands, got 2".to_string()));
+        assert_eq!(error("OUT 5"), (1, "operand '5' needs a mode sigil (@, # or %)".to_string()));
+        assert_eq!(error("a: HALT\n; comment\na: HALT"), (3, "duplicate label 'a'".to_string()));
+        assert_eq!(error("HALT\nJZ #0, #nowhere\nHALT"), (2, "undefined label 'nowhere'".to_string()));
+        assert_eq!(error("HALT\n5 HALT"), (2, "address column says 5 but line is at 1".to_string()));
+        assert_eq!(error("HALT\nDATA"), (2, "DATA needs at least one value".to_string()));
+        assert_eq!(error("OUT #x+y"), (1, "invalid offset in 'x+y'".to_string()));
+        assert_eq!(assemble("HALT\nFOO").unwrap_err().to_string(), "line 2: unknown mnemonic 'FOO'");
+    }
+}
+
+mod networking {
+    use super::*;
+
+    use super::network::{Network, NetworkEvent, Packet, NAT_ADDRESS};
+
+    /// Address 0 sends (1, 10, 20) when it boots. Every machine then sends each packet it
+    /// receives on to the NAT with y bumped, and counts the -1s it reads in its last cell.
+    const RELAY: &str = "
+                IN @addr
+                JNZ @addr, #loop
+                OUT #1
+                OUT #10
+                OUT #20
+        loop:   IN @x
+                EQ @x, #-1, @t
+                JZ @t, #got
+                ADD @idles, #1, @idles
+                JZ #0, #loop
+        got:    IN @y
+                ADD @y, #1, @y
+                OUT #255
+                OUT @x
+                OUT @y
+                JZ #0, #loop
+        addr:   DATA 0
+        x:      DATA 0
+        y:      DATA 0
+        t:      DATA 0
+        idles:  DATA 0
+    ";
+
+    fn relay() -> Program { asm::assemble(RELAY).unwrap() }
+
+    #[test]
+    fn routing_and_idle_detection() {
+        let program = relay();
+        let mut net = Network::new(&program, 3);
+
+        assert_eq!(net.tick(), Ok(vec![
+            NetworkEvent::Sent(Packet { src: 0, dest: 1, x: 10, y: 20 }),
+            NetworkEvent::Sent(Packet { src: 1, dest: NAT_ADDRESS, x: 10, y: 21 }),
+        ]));
+        assert_eq!(net.tick(), Ok(vec![NetworkEvent::Idle]));
+        assert_eq!(net.tick(), Ok(vec![NetworkEvent::Idle]));
+
+        // Every machine read its address on the first tick and -1 on each of the others
+        let idles = program.len() - 1;
+        assert_eq!((0..3).map(|a| net.machine(a).peek(idles)).collect::<Vec<_>>(), [2, 2, 2]);
+    }
+
+    #[test]
+    fn nat_resends_to_address_zero_when_idle() {
+        let mut net = Network::new(&relay(), 3);
+        net.enable_nat();
+
+        let mut resent = vec![];
+        let y = net.run_until(|e| match e {
+            NetworkEvent::NatResent(p) => {
+                assert_eq!((p.src, p.dest, p.x), (NAT_ADDRESS, 0, 10));
+                resent.push(p.y);
+                if resent.len() == 3 { Some(p.y) } else { None }
+            }
+            _ => None,
+        });
+
+        assert_eq!(y, Ok(Some(23)));
+        assert_eq!(resent, [21, 22, 23]);
+    }
+
+    #[test]
+    fn stops_once_every_machine_has_halted() {
+        let mut net = Network::new(&[3, 0, 99], 4);
+        net.enable_nat();
+        assert_eq!(net.run_until(|_| None::<()>), Ok(None));
+    }
+}
+
+mod loading {
+    use super::loader::{self, Format, LoadError};
+
+    const QUINE: [i64; 16] = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+
+    #[test]
+    fn text_tolerates_whitespace() {
+        assert_eq!(loader::parse("1,0,0,0,99\n").unwrap(), [1, 0, 0, 0, 99]);
+        assert_eq!(loader::parse("\r\n 1, 0 ,\r\n\r\n0,\t0,99,\r\n").unwrap(), [1, 0, 0, 0, 99]);
+        assert_eq!(loader::parse(" \n").unwrap(), []);
+    }
+
+    #[test]
+    fn text_reports_bad_tokens() {
+        match loader::parse("1,2,\n3,x4,5") {
+            Err(LoadError::BadToken { offset: 7, line: 2, column: 3, token }) => assert_eq!(token, "x4"),
+            r => panic!("{:?}", r),
+        }
+        match loader::parse("1,,2") {
+            Err(LoadError::BadToken { offset: 2, line: 1, column: 3, token }) => assert!(token.is_empty()),
+            r => panic!("{:?}", r),
+        }
+        assert!(loader::parse("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        for &format in &[Format::Text, Format::Binary, Format::Listing] {
+            let mut bytes = vec![];
+            loader::save(&mut bytes, &QUINE, format).unwrap();
+            assert_eq!(loader::load(&bytes[..], format).unwrap(), QUINE, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn binary_is_recognized_whatever_the_format() {
+        let mut bytes = vec![];
+        loader::write_binary(&mut bytes, &QUINE).unwrap();
+        assert_eq!(loader::load(&bytes[..], Format::Text).unwrap(), QUINE);
+        assert!(loader::load(&bytes[..bytes.len() - 1], Format::Text).is_err());
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(Format::from_path("patched.icb"), Format::Binary);
+        assert_eq!(Format::from_path("patched.lst"), Format::Listing);
+        assert_eq!(Format::from_path("input.txt"), Format::Text);
+    }
+}
+
+mod snapshots {
+    use super::*;
+
+    use super::snapshot::SnapshotError;
+    use super::varint::write_varint;
+
+    /// A version 2 snapshot of a machine with the given program length and pc, nothing queued,
+    /// and non-zero cells at the given address gaps.
+    fn snapshot(program_len: i64, pc: i64, gaps: &[i64]) -> Vec<u8> {
+        let mut bytes = b"ICSNAP".to_vec();
+        for &x in [2, program_len, pc, 0, 0, 0, 0, 0, 0, gaps.len() as i64].iter().chain(gaps.iter().flat_map(|g| vec![g, &1])) {
+            write_varint(&mut bytes, x).unwrap();
+        }
+        bytes
+    }
+
+    fn corrupt(bytes: &[u8]) -> &'static str {
+        match Intcode::load(bytes) {
+            Err(SnapshotError::Corrupt(what)) => what,
+            r => panic!("expected a corrupt snapshot, got {:?}", r.map(|m| m.pc())),
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let quine = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        let mut m = Intcode::new(&quine, Some(&[4, -5]));
+        m.poke(1_000_000, -7);
+        (0..20).for_each(|_| m.do_cycle().unwrap());
+
+        let mut bytes = vec![];
+        m.save(&mut bytes).unwrap();
+        let mut loaded = Intcode::load(&bytes[..]).unwrap();
+
+        assert_eq!((loaded.pc(), loaded.relative_base(), loaded.cycles(), loaded.is_halted()), (m.pc(), m.relative_base(), m.cycles(), false));
+        assert_eq!((&loaded.input, &loaded.output), (&m.input, &m.output));
+        assert_eq!((loaded.program(), loaded.peek(100), loaded.peek(1_000_000)), (m.program(), m.peek(100), -7));
+
+        // Both carry on the same way
+        m.run_til_halt().unwrap();
+        loaded.run_til_halt().unwrap();
+        assert_eq!(loaded.output, m.output);
+        assert_eq!(loaded.output, quine);
+    }
+
+    #[test]
+    fn rejects_corrupt_snapshots() {
+        assert!(Intcode::load(&snapshot(5, 0, &[0, 4])[..]).is_ok());
+        assert_eq!(corrupt(&snapshot(5, 0, &[1 << 62, 1 << 62])), "address out of range");
+        assert_eq!(corrupt(&snapshot(5, 0, &[i64::MAX, i64::MAX])), "address out of range");
+        assert_eq!(corrupt(&snapshot(i64::MAX, i64::MAX - 1, &[])), "program too long");
+        assert_eq!(corrupt(&snapshot(-1, 0, &[])), "negative program length");
+        let truncated = snapshot(5, 0, &[0, 4]);
+        assert!(Intcode::load(&truncated[..truncated.len() - 1]).is_err());
+    }
+}
+
+mod history {
+    use super::*;
+
+    /// Reads a value into cell 13, doubles it, adds two and outputs the result.
+    const PROGRAM: [i64; 14] = [3, 13, 1002, 13, 2, 13, 1001, 13, 2, 13, 4, 13, 99, 0];
+
+    #[test]
+    fn step_back_undoes_memory_io_and_registers() {
+        let mut m = Intcode::new(&PROGRAM, Some(&[5]));
+        m.enable_history(100);
+        m.run_til_halt().unwrap();
+        assert_eq!((m.output.clone(), m.history_len()), (VecDeque::from(vec![12]), 5));
+
+        assert!(m.step_back());
+        assert_eq!((m.pc(), m.cycles(), m.is_halted()), (12, 4, false));
+
+        while m.step_back() {}
+        assert_eq!((m.pc(), m.cycles(), m.relative_base()), (0, 0, 0));
+        assert_eq!(m.program(), PROGRAM);
+        assert_eq!((m.input.clone(), m.output.clone()), (VecDeque::from(vec![5]), VecDeque::new()));
+
+        // Replaying from the start does the same again
+        m.run_til_halt().unwrap();
+        assert_eq!(m.output, VecDeque::from(vec![12]));
+        assert!(m.rewind_to(2));
+        assert_eq!((m.pc(), m.peek(13)), (6, 10));
+    }
+
+    #[test]
+    fn run_back_to_write_stops_just_before_each_write() {
+        let mut m = Intcode::new(&PROGRAM, Some(&[5]));
+        m.enable_history(100);
+        m.run_til_halt().unwrap();
+
+        assert!(m.run_back_to_write(13));
+        assert_eq!((m.pc(), m.cycles(), m.peek(13)), (6, 2, 10));
+        assert!(m.run_back_to_write(13));
+        assert_eq!((m.pc(), m.cycles(), m.peek(13)), (2, 1, 5));
+        assert!(m.run_back_to_write(13));
+        assert_eq!((m.pc(), m.cycles(), m.peek(13)), (0, 0, 0));
+        assert!(!m.run_back_to_write(13));
+
+        // An address nothing wrote to rewinds all the way
+        m.run_til_halt().unwrap();
+        assert!(!m.run_back_to_write(12));
+        assert_eq!(m.cycles(), 0);
+    }
+
+    #[test]
+    fn keeps_only_the_last_instructions() {
+        let mut m = Intcode::new(&PROGRAM, Some(&[5]));
+        m.enable_history(2);
+        m.run_til_halt().unwrap();
+        assert_eq!(m.history_len(), 2);
+
+        assert!(m.step_back() && m.step_back() && !m.step_back());
+        assert_eq!((m.pc(), m.cycles(), m.peek(13)), (10, 3, 12));
+        assert!(!m.rewind_to(0));
+        assert_eq!(m.cycles(), 3);
+
+        m.run_til_halt().unwrap();
+        m.enable_history(1);
+        assert_eq!(m.history_len(), 1);
+        m.disable_history();
+        assert_eq!((m.history_len(), m.history_limit()), (0, None));
+    }
+}
+
+mod control_flow {
+    use super::*;
+
+    use super::cfg::{Cfg, Edge, Exit};
+
+    fn exit(cfg: &Cfg, address: usize) -> Exit { cfg.graph[cfg.block_at(address).unwrap()].exit }
+
+    fn edge(cfg: &Cfg, from: usize, to: usize) -> Option<Edge> {
+        let e = cfg.graph.find_edge(cfg.block_at(from)?, cfg.block_at(to)?)?;
+        Some(cfg.graph[e])
+    }
+
+    #[test]
+    fn a_loop_is_one_component() {
+        // Counts cell 20 down from 3, outputting it until it reaches zero
+        let mut program = vec![1001, 20, -1, 20, 1006, 20, 12, 4, 20, 1105, 1, 0, 99];
+        program.resize(20, 0);
+        program.push(3);
+        assert_eq!(outputs(&program, &[]), [2, 1]);
+
+        let cfg = Cfg::new(&program);
+        assert_eq!(cfg.graph.node_count(), 3);
+        assert_eq!(cfg.loops(), [[0, 7]]);
+        assert_eq!((exit(&cfg, 0), exit(&cfg, 7), exit(&cfg, 12)), (Exit::Jump, Exit::Jump, Exit::Halt));
+        assert!(cfg.reaches(7, 0) && cfg.reaches(0, 12) && cfg.reaches(7, 12));
+        assert!(!cfg.reaches(12, 0));
+        assert!(cfg.indirect_jumps.is_empty());
+    }
+
+    #[test]
+    fn jump_targets_start_blocks() {
+        // Skips the increment if cell 11 is zero
+        let program = [1006, 11, 7, 1001, 11, 1, 11, 4, 11, 99, 0, 0];
+        let cfg = Cfg::new(&program);
+
+        let starts = cfg.graph.node_indices().map(|n| cfg.graph[n].start).collect::<Vec<_>>();
+        assert_eq!(starts, [0, 3, 7]);
+        assert_eq!(cfg.graph[cfg.block_at(3).unwrap()].instructions, ["ADD  @11, #1, @11"]);
+        assert_eq!(exit(&cfg, 3), Exit::Fallthrough);
+
+        assert_eq!(edge(&cfg, 0, 3), Some(Edge::Fallthrough));
+        assert_eq!(edge(&cfg, 0, 7), Some(Edge::Jump));
+        assert_eq!(edge(&cfg, 3, 7), Some(Edge::Fallthrough));
+        assert_eq!(edge(&cfg, 3, 0), None);
+        assert!(cfg.loops().is_empty());
+    }
+
+    #[test]
+    fn indirect_jumps_are_left_unresolved() {
+        // Jumps to wherever the input says, the code at 5 and 6 only being reachable that way
+        let program = [3, 9, 105, 1, 9, 99, 104, 7, 99, 0];
+        assert_eq!(outputs(&program, &[6]), [7]);
+
+        let cfg = Cfg::new(&program);
+        assert_eq!(cfg.indirect_jumps, [2]);
+        assert_eq!(cfg.graph.node_count(), 1);
+        assert_eq!(exit(&cfg, 0), Exit::IndirectJump);
+        assert_eq!((cfg.block_at(5), cfg.block_at(6)), (None, None));
+        assert!(!cfg.reaches(0, 6));
+    }
+}
+
+mod debugging {
+    use super::*;
+
+    use super::debugger::Debugger;
+
+    /// Reads a value into cell 13, doubles it, adds two and outputs the result.
+    const PROGRAM: [i64; 14] = [3, 13, 1002, 13, 2, 13, 1001, 13, 2, 13, 4, 13, 99, 0];
+
+    /// Runs `line` and returns what the debugger printed.
+    fn command(debugger: &mut Debugger, line: &str) -> String {
+        let mut out = vec![];
+        assert!(debugger.command(line, &mut out).unwrap());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn step_and_input() {
+        let mut db = Debugger::new(Intcode::new(&PROGRAM, None));
+        assert_eq!(command(&mut db, "step"), "waiting for input\n=>      0  IN   @13\n");
+        assert_eq!(command(&mut db, "input 5"), "");
+        assert_eq!(command(&mut db, "step 3"), "=>     10  OUT  @13\n");
+        assert_eq!(command(&mut db, "step 5"), "halted\noutput: [12]\n");
+        assert_eq!(db.machine().output, [12]);
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let mut db = Debugger::new(Intcode::new(&PROGRAM, Some(&[5])));
+        assert_eq!(command(&mut db, "break 10"), "");
+        assert_eq!(command(&mut db, "continue"), "breakpoint at 10\n=>     10  OUT  @13\n");
+        assert_eq!(command(&mut db, "continue"), "halted\noutput: [12]\n");
+        assert_eq!(command(&mut db, "delete 10"), "");
+        assert_eq!(command(&mut db, "delete 10"), "no breakpoint at 10\n");
+    }
+
+    #[test]
+    fn mem_and_set() {
+        let mut db = Debugger::new(Intcode::new(&PROGRAM, Some(&[5])));
+        assert_eq!(command(&mut db, "mem 8 3"), "     8:          2         13          4\n");
+        assert_eq!(command(&mut db, "set 4 3 7"), "");
+        assert_eq!(command(&mut db, "mem 0 10"), concat!(
+            "     0:          3         13       1002         13          3          7       1001         13\n",
+            "     8:          2         13\n",
+        ));
+        assert_eq!(command(&mut db, "set -1 3"), "invalid address\n");
+        assert_eq!(command(&mut db, "continue"), "halted\noutput: [2]\n");
+    }
+
+    #[test]
+    fn back_and_lastwrite() {
+        let mut db = Debugger::new(Intcode::new(&PROGRAM, Some(&[5])));
+        assert_eq!(command(&mut db, "continue"), "halted\noutput: [12]\n");
+        assert_eq!(command(&mut db, "back 2"), "=>     10  OUT  @13\n");
+        assert_eq!(command(&mut db, "lastwrite 13"), "=>      6  ADD  @13, #2, @13\n");
+        assert_eq!(db.machine().peek(13), 10);
+        assert_eq!(command(&mut db, "back 5"), "reached the start of the history\n=>      0  IN   @13\n");
+        assert_eq!(command(&mut db, "lastwrite 13"), "no recorded write to 13\n=>      0  IN   @13\n");
+        assert_eq!(command(&mut db, "continue"), "halted\noutput: [12]\n");
+    }
+}
+
+mod solving {
+    use std::ops::RangeInclusive;
+
+    use super::symbolic::{SymbolicError, SymbolicMachine};
+
+    const M: i64 = i64::MAX;
+
+    /// Solves `sum(coefficients[i] * xs[i]) == target` for inputs `xs[i]` in `ranges[i]`, by
+    /// running a program that reads the inputs and outputs the sum.
+    fn solve(coefficients: &[i64], ranges: &[RangeInclusive<i64>], target: i64) -> Result<Option<Vec<i64>>, SymbolicError> {
+        let mut program = vec![1101, 0, 0, 99];
+        for (i, &c) in coefficients.iter().enumerate() {
+            let cell = 100 + i as i64;
+            program.extend_from_slice(&[3, cell, 1002, cell, c, cell, 1, 99, cell, 99]);
+        }
+        program.extend_from_slice(&[4, 99, 99]);
+
+        let mut machine = SymbolicMachine::new(&program);
+        ranges.iter().for_each(|r| { machine.push_symbolic_input(r.clone()); });
+        machine.run()?;
+        machine.solve(&machine.outputs()[0], target)
+    }
+
+    #[test]
+    fn writes_far_out_in_memory() {
+        let far = 1 << 40;
+        let mut machine = SymbolicMachine::new(&[1101, 1, 2, far, 99]);
+        machine.run().unwrap();
+        assert_eq!((machine.peek(far as usize).as_const(), machine.peek(far as usize + 1).as_const()), (Some(3), Some(0)));
+    }
+
+    #[test]
+    fn single_symbol() {
+        assert_eq!(solve(&[3], &[0..=10], 12), Ok(Some(vec![4])));
+        assert_eq!(solve(&[-3], &[-10..=10], 12), Ok(Some(vec![-4])));
+        assert_eq!(solve(&[3], &[0..=10], 13), Ok(None));
+        assert_eq!(solve(&[3], &[0..=10], 60), Ok(None));
+        assert_eq!(solve(&[0], &[7..=10], 0), Ok(Some(vec![7])));
+        assert_eq!(solve(&[0], &[7..=10], 1), Ok(None));
+    }
+
+    #[test]
+    fn two_symbols() {
+        assert_eq!(solve(&[6, 10], &[-10..=10, -10..=10], 8), Ok(Some(vec![-7, 5])));
+        assert_eq!(solve(&[-6, 10], &[-10..=10, -10..=10], 8), Ok(Some(vec![-8, -4])));
+        assert_eq!(solve(&[-6, -10], &[-10..=10, -10..=10], 8), Ok(Some(vec![-8, 4])));
+        // No solution at all, and none in range
+        assert_eq!(solve(&[6, 10], &[-10..=10, -10..=10], 7), Ok(None));
+        assert_eq!(solve(&[6, 10], &[-10..=10, -10..=10], 1000), Ok(None));
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn empty_ranges() {
+        assert_eq!(solve(&[0], &[5..=4], 0), Ok(None));
+        assert_eq!(solve(&[1], &[5..=4], 5), Ok(None));
+        assert_eq!(solve(&[1, 1], &[0..=10, 5..=4], 5), Ok(None));
+        assert_eq!(solve(&[1, 1, 1], &[5..=4, 0..=10, 0..=10], 5), Ok(None));
+        assert_eq!(solve(&[1, 1, 1], &[0..=10, 0..=10, 5..=4], 5), Ok(None));
+    }
+
+    #[test]
+    fn large_values() {
+        // The first symbol makes the remaining target about 2^126, far more than i128 can hold
+        // once multiplied by a Bezout coefficient of 2 and M
+        assert_eq!(solve(&[M, 2, M], &[M - 1..=M, i64::MIN..=M, i64::MIN..=M], 0), Ok(Some(vec![M - 1, -M, 3 - M])));
+        assert_eq!(solve(&[M, M, M, 1, 1], &[M..=M, M..=M, M..=M, i64::MIN..=M, i64::MIN..=M], 0), Err(SymbolicError::Overflow));
+    }
+}
+
+#[test]
+fn fuzzed_programs_agree_with_the_reference() {
+    for seed in 0..4 {
+        if let Err(mismatch) = fuzz::fuzz(seed, 500, 1000) {
+            panic!("{}", mismatch);
+        }
+    }
+}
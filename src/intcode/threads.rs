@@ -0,0 +1,196 @@
+use std::iter;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::{Intcode, IntcodeError, Program, RunState};
+
+struct Node {
+    program: Program,
+    tx: Sender<i64>,
+    rx: Receiver<i64>,
+    consumers: Vec<Sender<i64>>,
+    /// The nodes among `consumers`, as opposed to taps.
+    feeds: Vec<usize>,
+}
+
+/// Machines connected by channels, each to run on its own thread. Build it up with `add` and
+/// `connect`, then `spawn` it.
+///
+/// Once any machine halts or faults, the topology shuts down. A machine stops when it needs
+/// input that can no longer arrive, because every machine feeding it has stopped, or when it
+/// outputs and every machine it feeds has stopped. Values already sent are read first, so a
+/// feedback loop runs to the end. Values fed from outside after that may be ignored.
+pub struct Topology {
+    nodes: Vec<Node>,
+}
+
+impl Topology {
+    pub fn new() -> Self { Topology { nodes: vec![] } }
+
+    /// Adds a machine running `program` with `init_input` queued, and returns its node number.
+    pub fn add(&mut self, program: &[i64], init_input: &[i64]) -> usize {
+        let (tx, rx) = channel();
+        for &x in init_input {
+            tx.send(x).unwrap();
+        }
+
+        self.nodes.push(Node { program: program.to_vec(), tx, rx, consumers: vec![], feeds: vec![] });
+        self.nodes.len() - 1
+    }
+
+    /// Sends every output of node `from` to the input of node `to`.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        let tx = self.feed(to);
+        self.nodes[from].consumers.push(tx);
+        self.nodes[from].feeds.push(to);
+    }
+
+    /// A sender for feeding node `node` from outside, e.g. with the first value of a loop.
+    pub fn feed(&self, node: usize) -> Sender<i64> { self.nodes[node].tx.clone() }
+
+    /// A receiver of every output of node `node`, in addition to the nodes it's connected to.
+    pub fn tap(&mut self, node: usize) -> Receiver<i64> {
+        let (tx, rx) = channel();
+        self.nodes[node].consumers.push(tx);
+        rx
+    }
+
+    /// Starts one thread per machine.
+    pub fn spawn(self) -> Running {
+        let shutdown = Arc::new(Shutdown::new(&self.nodes));
+
+        let handles = self
+            .nodes
+            .into_iter()
+            .enumerate()
+            .map(|(id, node)| {
+                let shutdown = Arc::clone(&shutdown);
+                let mut machine = Intcode::new(&node.program, None);
+
+                thread::spawn(move || {
+                    let result = run_node(id, &mut machine, &node.rx, &node.consumers, &shutdown);
+                    shutdown.stopped(id);
+                    result
+                })
+            })
+            .collect();
+
+        Running { handles }
+    }
+}
+
+impl Default for Topology {
+    fn default() -> Self { Topology::new() }
+}
+
+/// Tells the threads of a spawned `Topology` when to stop.
+struct Shutdown {
+    /// For each node, the nodes it feeds and the nodes feeding it.
+    feeds: Vec<Vec<usize>>,
+    fed_by: Vec<Vec<usize>>,
+    state: Mutex<ShutdownState>,
+}
+
+struct ShutdownState {
+    stopping: bool,
+    running: Vec<bool>,
+    /// For each node, a sender to wake it up with once no more input can arrive. It's taken when
+    /// used, so the wake-up value is always the last one a machine sends to the node.
+    wake: Vec<Option<Sender<i64>>>,
+}
+
+impl Shutdown {
+    fn new(nodes: &[Node]) -> Self {
+        let feeds = nodes.iter().map(|n| n.feeds.clone()).collect::<Vec<_>>();
+        let fed_by = (0..nodes.len()).map(|id| (0..nodes.len()).filter(|&n| feeds[n].contains(&id)).collect()).collect();
+        let state = ShutdownState {
+            stopping: false,
+            running: vec![true; nodes.len()],
+            wake: nodes.iter().map(|n| Some(n.tx.clone())).collect(),
+        };
+
+        Shutdown { feeds, fed_by, state: Mutex::new(state) }
+    }
+
+    /// Records that `node` has stopped, which starts the shutdown, and wakes up the nodes that
+    /// can no longer get any input.
+    fn stopped(&self, node: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.stopping = true;
+        state.running[node] = false;
+
+        for id in 0..self.fed_by.len() {
+            if self.fed_by[id].iter().all(|&n| !state.running[n]) {
+                if let Some(tx) = state.wake[id].take() {
+                    let _ = tx.send(0);
+                }
+            }
+        }
+    }
+
+    /// Whether `node` has been sent its wake-up value.
+    fn is_starved(&self, node: usize) -> bool { self.state.lock().unwrap().wake[node].is_none() }
+
+    /// Whether the topology is shutting down and every node `node` feeds has stopped.
+    fn is_unheard(&self, node: usize) -> bool {
+        let state = self.state.lock().unwrap();
+        state.stopping && self.feeds[node].iter().all(|&n| !state.running[n])
+    }
+}
+
+/// Runs until the machine halts, or the shutdown stops it.
+fn run_node(id: usize, machine: &mut Intcode, rx: &Receiver<i64>, consumers: &[Sender<i64>], shutdown: &Shutdown) -> Result<(), IntcodeError> {
+    let mut starved = false;
+
+    loop {
+        match machine.run()? {
+            RunState::Output(x) => {
+                // Checked first, as a consumer that is still running may need this value before
+                // it stops
+                let unheard = shutdown.is_unheard(id);
+
+                // Consumers that have shut down are skipped
+                for tx in consumers {
+                    let _ = tx.send(x);
+                }
+
+                if unheard {
+                    return Ok(());
+                }
+            }
+            RunState::Watchpoint(_) => {}
+            RunState::Halted => return Ok(()),
+            RunState::NeedsInput if starved => return Ok(()),
+            RunState::NeedsInput => match rx.recv() {
+                Ok(x) if !shutdown.is_starved(id) => machine.input.push_back(x),
+                Ok(x) => {
+                    // Everything queued up to the wake-up value was sent before it
+                    let mut queued = iter::once(x).chain(rx.try_iter()).collect::<Vec<_>>();
+                    queued.pop();
+                    machine.input.extend(queued);
+                    starved = true;
+                }
+                Err(_) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// The threads of a spawned `Topology`.
+pub struct Running {
+    handles: Vec<JoinHandle<Result<(), IntcodeError>>>,
+}
+
+impl Running {
+    /// Waits for every machine to stop, and returns the fault of the first node that had one.
+    pub fn join(self) -> Result<(), IntcodeError> {
+        let results = self
+            .handles
+            .into_iter()
+            .map(|h| h.join().expect("Intcode thread panicked"))
+            .collect::<Vec<_>>();
+
+        results.into_iter().collect()
+    }
+}
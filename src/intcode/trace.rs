@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+
+use super::varint::write_varint;
+use super::{InputSource, Intcode, Op, OutputSink, Word};
+
+/// A record of one executed instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub instruction: i64,
+    /// The decoded instruction as it appears in a disassembly, e.g. `ADD @4, #3, %1`.
+    pub op: String,
+    /// The value of every operand that is read, and the resolved address of the one written to.
+    pub operands: Vec<i64>,
+    /// The address and value written, if the instruction wrote to memory.
+    pub write: Option<(usize, i64)>,
+    /// The relative base after the instruction.
+    pub relative_base: i64,
+}
+
+impl TraceEntry {
+    /// Starts an entry for `op`, which is about to be executed by `machine`. Values that don't
+    /// fit in an `i64` are clamped to its range.
+    pub(super) fn before<W: Word, I: InputSource<W>, O: OutputSink<W>>(machine: &Intcode<W, I, O>, op: &Op<W>) -> Self {
+        let (params, dst) = (op.params(), op.writes());
+
+        // The written parameter is always the last one
+        let operands = params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| match dst {
+                Some(_) if i == params.len() - 1 => machine.resolve(p).map(|a| a as i64),
+                _ => machine.value(p).map(|x| x.saturate()),
+            })
+            .map(|x| x.unwrap_or(0))
+            .collect();
+
+        TraceEntry {
+            pc: machine.pc,
+            instruction: machine.read_mem(machine.pc).saturate(),
+            op: machine.instruction_at(machine.pc).map(|(text, _)| text).unwrap_or_default(),
+            operands,
+            write: dst.and_then(|d| machine.resolve(&d).ok()).map(|a| (a, 0)),
+            relative_base: machine.relative_base,
+        }
+    }
+
+    /// Completes the entry once the instruction has executed.
+    pub(super) fn after<W: Word, I: InputSource<W>, O: OutputSink<W>>(mut self, machine: &Intcode<W, I, O>) -> Self {
+        self.write = self.write.map(|(a, _)| (a, machine.read_mem(a).saturate()));
+        self.relative_base = machine.relative_base;
+        self
+    }
+
+    fn to_json(&self, n: u64) -> String {
+        let operands = self.operands.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",");
+        let write = match self.write {
+            Some((a, x)) => format!("[{},{}]", a, x),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"n\":{},\"pc\":{},\"instruction\":{},\"op\":\"{}\",\"operands\":[{}],\"write\":{},\"relative_base\":{}}}",
+            n, self.pc, self.instruction, self.op, operands, write, self.relative_base
+        )
+    }
+
+    fn write_binary<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_varint(out, self.pc as i64)?;
+        write_varint(out, self.instruction)?;
+        write_varint(out, self.operands.len() as i64)?;
+        for &x in &self.operands {
+            write_varint(out, x)?;
+        }
+
+        match self.write {
+            Some((a, x)) => {
+                write_varint(out, 1)?;
+                write_varint(out, a as i64)?;
+                write_varint(out, x)?;
+            }
+            None => write_varint(out, 0)?,
+        }
+
+        write_varint(out, self.relative_base)
+    }
+}
+
+/// Receives an entry for every instruction executed by the machine it is attached to.
+pub trait Tracer {
+    fn record(&mut self, entry: &TraceEntry);
+
+    /// Flushes the trace, reporting the first error hit while recording.
+    fn finish(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// Collects the trace in memory.
+impl Tracer for Vec<TraceEntry> {
+    fn record(&mut self, entry: &TraceEntry) {
+        self.push(entry.clone());
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// A `ICTRACE1` header followed by one record per instruction, every field a zigzag
+    /// varint: pc, instruction, operand count, operands, write flag, [address, value], relative
+    /// base. The rendered op is left out as it follows from the instruction.
+    Binary,
+}
+
+/// Streams the trace to a writer. Recording stops at the first I/O error, which is reported by
+/// `finish`.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    format: TraceFormat,
+    n: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W, format: TraceFormat) -> Self {
+        let error = match format {
+            TraceFormat::Binary => out.write_all(b"ICTRACE1").err(),
+            TraceFormat::JsonLines => None,
+        };
+
+        TraceWriter { out, format, n: 0, error }
+    }
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn record(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let res = match self.format {
+            TraceFormat::JsonLines => writeln!(self.out, "{}", entry.to_json(self.n)),
+            TraceFormat::Binary => entry.write_binary(&mut self.out),
+        };
+
+        self.n += 1;
+        self.error = res.err();
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush(),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::io::{self, Read, Write};
+
+/// Writes `x` zigzag encoded as a LEB128 varint, so small magnitudes of either sign take a
+/// single byte.
+pub fn write_varint<W: Write>(out: &mut W, x: i64) -> io::Result<()> {
+    let mut v = ((x << 1) ^ (x >> 63)) as u64;
+
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+
+        if v == 0 {
+            return out.write_all(&[byte]);
+        }
+
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Reads a varint written by `write_varint`.
+pub fn read_varint<R: Read>(input: &mut R) -> io::Result<i64> {
+    let mut v = 0_u64;
+
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+
+        v |= u64::from(byte[0] & 0x7f) << shift;
+
+        if byte[0] & 0x80 == 0 {
+            return Ok((v >> 1) as i64 ^ -((v & 1) as i64));
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint longer than 64 bits"))
+}
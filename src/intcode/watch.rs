@@ -0,0 +1,82 @@
+use std::ops::RangeInclusive;
+
+use super::{InputSource, Intcode, OutputSink, Word};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<usize>,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, address: usize, access: Access) -> bool {
+        let kind = matches!(
+            (self.kind, access),
+            (WatchKind::ReadWrite, _) | (WatchKind::Read, Access::Read) | (WatchKind::Write, Access::Write)
+        );
+
+        kind && self.range.contains(&address)
+    }
+}
+
+pub type WatchCallback = Box<dyn FnMut(&WatchHit) + Send>;
+
+/// An operand access that matched a watchpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    /// The instruction that made the access.
+    pub pc: usize,
+    pub instruction: i64,
+    pub address: usize,
+    pub access: Access,
+    /// The value read, or the value written, clamped to the `i64` range.
+    pub value: i64,
+}
+
+impl<W: Word, I: InputSource<W>, O: OutputSink<W>> Intcode<W, I, O> {
+    /// Watches operand reads and/or writes of the addresses in `range`. Instruction fetches are
+    /// not watched. By default a hit makes `run` return `RunState::Watchpoint` once the
+    /// instruction has completed; see `set_watch_callback`.
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<usize>, kind: WatchKind) {
+        self.hooks.watchpoints.push(Watchpoint { range, kind });
+    }
+
+    /// Removes every watchpoint covering `address`. Returns whether there were any.
+    pub fn remove_watchpoints(&mut self, address: usize) -> bool {
+        let before = self.hooks.watchpoints.len();
+        self.hooks.watchpoints.retain(|w| !w.range.contains(&address));
+
+        self.hooks.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] { &self.hooks.watchpoints }
+
+    /// Hands watchpoint hits to `callback` instead of pausing execution.
+    pub fn set_watch_callback(&mut self, callback: WatchCallback) {
+        self.hooks.watch_callback = Some(callback);
+    }
+
+    pub(super) fn watch(&mut self, address: usize, access: Access, value: &W) {
+        if self.hooks.watchpoints.iter().any(|w| w.matches(address, access)) {
+            let hit = WatchHit { pc: self.pc, instruction: self.instruction(), address, access, value: value.saturate() };
+
+            match self.hooks.watch_callback.as_mut() {
+                Some(callback) => callback(&hit),
+                None => self.hooks.watch_hits.push(hit),
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use num::bigint::BigInt;
+use num::traits::{ToPrimitive, Zero};
+
+/// The type of a memory cell. Opcodes, addresses and relative base adjustments must still fit
+/// in an `i64`; only the values being computed with can be wider.
+pub trait Word: Clone + PartialEq + PartialOrd + fmt::Debug {
+    fn from_i64(x: i64) -> Self;
+
+    /// The value as an `i64`, or `None` if it doesn't fit.
+    fn as_i64(&self) -> Option<i64>;
+
+    /// `None` if the sum isn't representable.
+    fn try_add(&self, other: &Self) -> Option<Self>;
+
+    /// `None` if the product isn't representable.
+    fn try_mul(&self, other: &Self) -> Option<Self>;
+
+    fn zero() -> Self { Self::from_i64(0) }
+
+    fn is_positive(&self) -> bool { *self > Self::zero() }
+
+    /// The value as an `i64`, clamped to its range. Errors, traces and watchpoint hits report
+    /// values this way.
+    fn saturate(&self) -> i64 {
+        match self.as_i64() {
+            Some(x) => x,
+            None if self.is_positive() => i64::MAX,
+            None => i64::MIN,
+        }
+    }
+}
+
+/// Plain machine integers. Overflow wraps around, in debug and release builds alike.
+impl Word for i64 {
+    fn from_i64(x: i64) -> Self { x }
+
+    fn as_i64(&self) -> Option<i64> { Some(*self) }
+
+    fn try_add(&self, other: &Self) -> Option<Self> { Some(self.wrapping_add(*other)) }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> { Some(self.wrapping_mul(*other)) }
+}
+
+/// 128-bit integers that fault with `IntcodeError::Overflow` once even they overflow.
+impl Word for i128 {
+    fn from_i64(x: i64) -> Self { x as i128 }
+
+    fn as_i64(&self) -> Option<i64> { i64::try_from(*self).ok() }
+
+    fn try_add(&self, other: &Self) -> Option<Self> { self.checked_add(*other) }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> { self.checked_mul(*other) }
+}
+
+/// Arbitrary precision integers, which never overflow.
+impl Word for BigInt {
+    fn from_i64(x: i64) -> Self { BigInt::from(x) }
+
+    fn as_i64(&self) -> Option<i64> { self.to_i64() }
+
+    fn try_add(&self, other: &Self) -> Option<Self> { Some(self + other) }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> { Some(self * other) }
+
+    fn zero() -> Self { <BigInt as Zero>::zero() }
+}
+
+/// 64-bit integers that fault with `IntcodeError::Overflow` instead of wrapping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Checked(pub i64);
+
+impl fmt::Display for Checked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Word for Checked {
+    fn from_i64(x: i64) -> Self { Checked(x) }
+
+    fn as_i64(&self) -> Option<i64> { Some(self.0) }
+
+    fn try_add(&self, other: &Self) -> Option<Self> { self.0.checked_add(other.0).map(Checked) }
+
+    fn try_mul(&self, other: &Self) -> Option<Self> { self.0.checked_mul(other.0).map(Checked) }
+}
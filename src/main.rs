@@ -1,9 +1,17 @@
 use std::env;
 use std::path::Path;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-mod intcode;
+use aoc_19::intcode;
+use aoc_19::intcode::compile::CompiledProgram;
+use aoc_19::intcode::loader;
+use aoc_19::intcode::network::{Network, NetworkEvent, NAT_ADDRESS};
+use aoc_19::intcode::snapshot::SnapshotError;
+use aoc_19::intcode::trace::{TraceFormat, TraceWriter};
 
 mod day01;
 mod day02;
@@ -33,14 +41,13 @@ fn parse_lines<T, F>(input: &str, parser: F) -> Vec<T> where F: Fn(&str) -> T {
 }
 
 fn parse_intcode_program(input_str: &str) -> intcode::Program {
-    input_str
-        .trim_end_matches('\n')
-        .split(',')
-        .map(|s| s.parse::<i64>().unwrap())
-        .collect()
+    loader::parse(input_str).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
 }
 
-fn main() {
+fn run_days() {
     let root = env::current_dir().unwrap().join("src");
     println!("Current dir: {:?}", root.to_str());
 
@@ -58,3 +65,224 @@ fn main() {
     day12::run(&read_file_to_string(root.join("day12").join("input.txt").into_boxed_path().as_ref()));
     day13::run(&read_file_to_string(root.join("day13").join("input.txt").into_boxed_path().as_ref()));
 }
+
+fn usage() -> ! {
+    eprintln!("Usage: aoc-19 [ascii <program> | asm <source> | disasm <program> | cfg <program> | debug <program|snapshot> [input...] | trace <program> <out.jsonl|out.bin> [input...] | bench <program> [input...] | network <program> [size] | profile <program> [input...] | fuzz [cases] [seed] | convert <program> <out>]\n\
+       A <program> can be text, a binary .icb file, a .lst/.asm/.ica listing, or - for standard input");
+    process::exit(1);
+}
+
+/// Loads the program named by the first argument, in any format `loader::load_path` accepts.
+fn program_arg(args: &[String]) -> intcode::Program {
+    let path = args.first().unwrap_or_else(|| usage());
+
+    loader::load_path(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn file_arg(args: &[String]) -> String {
+    match args.first() {
+        Some(path) => read_file_to_string(Path::new(path)),
+        None => usage(),
+    }
+}
+
+fn int_args(args: &[String]) -> Vec<i64> {
+    args.iter().map(|s| s.parse::<i64>().unwrap_or_else(|_| usage())).collect()
+}
+
+/// Loads the machine to debug, either from a snapshot file or from a program and its input.
+fn load_machine(args: &[String]) -> intcode::Intcode {
+    let path = args.first().unwrap_or_else(|| usage());
+    let from_program = || intcode::Intcode::new(&program_arg(args), Some(&int_args(&args[1..])));
+
+    if path == "-" {
+        return from_program();
+    }
+
+    let file = File::open(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+
+    match intcode::Intcode::load(io::BufReader::new(file)) {
+        Ok(machine) => machine,
+        Err(SnapshotError::NotASnapshot) | Err(SnapshotError::Io(_)) => from_program(),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn trace(args: &[String]) {
+    let program = program_arg(args);
+    let path = args.get(1).unwrap_or_else(|| usage());
+    let format = if path.ends_with(".bin") { TraceFormat::Binary } else { TraceFormat::JsonLines };
+
+    let mut computer = intcode::Intcode::new(&program, Some(&int_args(&args[2..])));
+    computer.set_tracer(Box::new(TraceWriter::new(io::BufWriter::new(File::create(path).unwrap()), format)));
+
+    if let Err(e) = computer.run_til_halt() {
+        eprintln!("{}", e);
+    }
+
+    computer.take_tracer().unwrap().finish().unwrap();
+    println!("{:?}", computer.output);
+}
+
+/// Times running a program to completion with and without the decode cache, and compiled.
+fn bench(args: &[String]) {
+    const RUNS: u32 = 20;
+
+    let program = program_arg(args);
+    let input = int_args(&args[1..]);
+
+    let time = |cache: bool| -> Duration {
+        let start = Instant::now();
+
+        for _ in 0..RUNS {
+            let mut computer = intcode::Intcode::new(&program, Some(&input));
+            computer.set_decode_cache(cache);
+            computer.run_til_halt().unwrap();
+        }
+
+        start.elapsed() / RUNS
+    };
+
+    let (uncached, cached) = (time(false), time(true));
+    println!("uncached: {:?}, cached: {:?}, speedup: {:.2}x", uncached, cached, uncached.as_secs_f64() / cached.as_secs_f64());
+
+    let start = Instant::now();
+    let compiled = CompiledProgram::new(&program);
+    for _ in 0..RUNS {
+        compiled.instantiate(Some(&input)).run_til_halt().unwrap();
+    }
+
+    let elapsed = start.elapsed() / RUNS;
+    println!("compiled: {:?}, speedup: {:.2}x", elapsed, uncached.as_secs_f64() / elapsed.as_secs_f64());
+}
+
+/// Runs a program until it halts or runs out of input, and reports where it spent its time.
+fn profile(args: &[String]) {
+    const TOP: usize = 20;
+
+    let mut computer = intcode::Intcode::new(&program_arg(args), Some(&int_args(&args[1..])));
+    computer.enable_profiler();
+
+    loop {
+        match computer.run() {
+            Ok(intcode::RunState::Halted) => break,
+            Ok(intcode::RunState::NeedsInput) => {
+                println!("stopped: waiting for input");
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!("stopped: {}", e);
+                break;
+            }
+        }
+    }
+
+    print!("{}", computer.profile_report(TOP).unwrap());
+}
+
+/// Checks randomly generated programs against the reference interpreter, and prints the first
+/// one that some execution path runs differently.
+fn fuzz(args: &[String]) {
+    const MAX_CYCLES: u64 = 1000;
+
+    let cases = args.first().map_or(10_000, |s| s.parse().unwrap_or_else(|_| usage()));
+    let seed = args.get(1).map_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64, |s| s.parse().unwrap_or_else(|_| usage()));
+
+    match intcode::fuzz::fuzz(seed, cases, MAX_CYCLES) {
+        Ok(overflowed) => println!("seed {}: {} cases agree, {} of them overflowing", seed, cases, overflowed),
+        Err(mismatch) => {
+            eprintln!("seed {}: {}", seed, mismatch);
+            process::exit(1);
+        }
+    }
+}
+
+/// Boots a network of machines, and reports the first packet sent to the NAT and the first value
+/// the NAT sends twice in a row.
+fn network(args: &[String]) {
+    let program = program_arg(args);
+    let size = args.get(1).map_or(50, |s| s.parse().unwrap_or_else(|_| usage()));
+
+    let mut net = Network::new(&program, size);
+    let first = net.run_until(|e| match e {
+        NetworkEvent::Sent(p) if p.dest == NAT_ADDRESS => Some(p.y),
+        _ => None,
+    });
+
+    let mut net = Network::new(&program, size);
+    net.enable_nat();
+    let mut last = None;
+    let repeated = net.run_until(|e| match e {
+        NetworkEvent::NatResent(p) if last == Some(p.y) => Some(p.y),
+        NetworkEvent::NatResent(p) => {
+            last = Some(p.y);
+            None
+        }
+        _ => None,
+    });
+
+    match (first, repeated) {
+        (Ok(Some(first)), Ok(Some(repeated))) => println!("first y sent to the NAT: {}, first y resent twice: {}", first, repeated),
+        (Ok(_), Ok(_)) => {
+            eprintln!("every machine halted");
+            process::exit(1);
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    match args.first().map(String::as_str) {
+        Some("ascii") => {
+            let stdin = io::stdin();
+            let mut computer = intcode::Intcode::new(&program_arg(&args[1..]), None);
+            if let Err(e) = computer.interact(stdin.lock(), io::stdout()) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some("asm") => match intcode::asm::assemble(&file_arg(&args[1..])) {
+            Ok(program) => println!("{}", program.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",")),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        Some("debug") => {
+            let stdin = io::stdin();
+            intcode::debugger::Debugger::new(load_machine(&args[1..]))
+                .repl(stdin.lock(), io::stdout())
+                .unwrap();
+        }
+        Some("trace") => trace(&args[1..]),
+        Some("bench") => bench(&args[1..]),
+        Some("network") => network(&args[1..]),
+        Some("profile") => profile(&args[1..]),
+        Some("fuzz") => fuzz(&args[1..]),
+        Some("convert") => {
+            let out = args.get(2).unwrap_or_else(|| usage());
+            if let Err(e) = loader::save_path(out, &program_arg(&args[1..])) {
+                eprintln!("{}: {}", out, e);
+                process::exit(1);
+            }
+        }
+        Some("cfg") => print!("{}", intcode::cfg::Cfg::new(&program_arg(&args[1..])).to_dot()),
+        Some("disasm") => print!("{}", intcode::disasm::listing(&program_arg(&args[1..]))),
+        _ => run_days(),
+    }
+}